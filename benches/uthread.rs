@@ -0,0 +1,78 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::convert::Infallible;
+use std::ops::ControlFlow;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use crossbeam_channel::Sender;
+use microservices::{UService, UThread};
+
+enum Msg {
+    Ping(u64),
+    Flush(Sender<u64>),
+}
+
+#[derive(Default)]
+struct Sink(u64);
+
+impl UService for Sink {
+    type Msg = Msg;
+    type Error = Infallible;
+    const NAME: &'static str = "sink";
+
+    fn process(&mut self, msg: Self::Msg) -> Result<ControlFlow<u8>, Self::Error> {
+        match msg {
+            Msg::Ping(val) => self.0 = self.0.wrapping_add(val),
+            Msg::Flush(sender) => sender.send(self.0).expect("bench thread is gone"),
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn terminate(&mut self) {}
+}
+
+fn send_and_flush(c: &mut Criterion) {
+    let thread = UThread::new(Sink::default(), None);
+    let sender = thread.sender();
+    let mut group = c.benchmark_group("uthread");
+    for count in [1u64, 100, 10_000] {
+        group.throughput(Throughput::Elements(count));
+        group.bench_with_input(BenchmarkId::new("send_and_flush", count), &count, |b, &count| {
+            b.iter(|| {
+                for val in 0..count {
+                    sender
+                        .send(Msg::Ping(val))
+                        .unwrap_or_else(|_| panic!("service is down"));
+                }
+                let (tx, rx) = crossbeam_channel::bounded(1);
+                sender
+                    .send(Msg::Flush(tx))
+                    .unwrap_or_else(|_| panic!("service is down"));
+                rx.recv().expect("service is down")
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, send_and_flush);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=RUSTC");
+    if env::var_os("CARGO_FEATURE_PLUGIN").is_none() {
+        return;
+    }
+    // Plugins can be loaded only if they are compiled by the same compiler as the host.
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    let output = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .expect("unable to run rustc");
+    let version = String::from_utf8(output.stdout).expect("rustc version is not UTF-8");
+    println!("cargo:rustc-env=MICROSERVICES_RUSTC_VERSION={}", version.trim());
+}
@@ -0,0 +1,104 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Load generator for a single service thread.
+//!
+//! Usage: `cargo run --release --features bench --example load [PRODUCERS] [MESSAGES]`
+
+use std::convert::Infallible;
+use std::ops::ControlFlow;
+use std::time::Instant;
+use std::{env, thread};
+
+use crossbeam_channel::Sender;
+use microservices::{UService, UThread};
+
+enum Msg {
+    Work(u64),
+    Flush(Sender<()>),
+}
+
+#[derive(Default)]
+struct Worker(u64);
+
+impl UService for Worker {
+    type Msg = Msg;
+    type Error = Infallible;
+    const NAME: &'static str = "worker";
+
+    fn process(&mut self, msg: Self::Msg) -> Result<ControlFlow<u8>, Self::Error> {
+        match msg {
+            Msg::Work(val) => self.0 = self.0.wrapping_mul(31).wrapping_add(val),
+            Msg::Flush(sender) => sender.send(()).expect("load generator is gone"),
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn terminate(&mut self) {}
+}
+
+fn arg(pos: usize, default: u64) -> u64 {
+    env::args()
+        .nth(pos)
+        .map(|s| s.parse().expect("arguments must be integer numbers"))
+        .unwrap_or(default)
+}
+
+fn main() {
+    let producers = arg(1, 4);
+    let messages = arg(2, 1_000_000);
+
+    let thread = UThread::new(Worker::default(), None);
+    let start = Instant::now();
+    thread::scope(|scope| {
+        for _ in 0..producers {
+            let sender = thread.sender();
+            scope.spawn(move || {
+                for val in 0..messages {
+                    sender
+                        .send(Msg::Work(val))
+                        .unwrap_or_else(|_| panic!("worker is down"));
+                }
+            });
+        }
+    });
+    let (tx, rx) = crossbeam_channel::bounded(1);
+    thread
+        .sender()
+        .send(Msg::Flush(tx))
+        .unwrap_or_else(|_| panic!("worker is down"));
+    rx.recv().expect("worker is down");
+    let elapsed = start.elapsed();
+
+    let stats = thread.stats();
+    let total = producers * messages;
+    println!("producers:    {producers}");
+    println!("messages:     {total}");
+    println!("wall time:    {elapsed:?}");
+    println!("wall rate:    {:.0} msg/s", total as f64 / elapsed.as_secs_f64());
+    println!("busy time:    {:?}", stats.busy);
+    if let Some(throughput) = stats.throughput() {
+        println!("service rate: {throughput:.0} msg/s");
+    }
+    if let Some(latency) = stats.mean_latency() {
+        println!("mean latency: {latency:?}");
+    }
+}
@@ -0,0 +1,187 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::{Any, TypeId, type_name};
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{UMulticast, USender, USubscription};
+
+type Topics = HashMap<TypeId, (&'static str, Box<dyn Any + Send>)>;
+
+/// In-process publish-subscribe bus delivering typed events to the services subscribed to the
+/// event type.
+///
+/// The bus is cheap to clone; all clones share the same subscriptions, so each service may keep
+/// its own handle to publish or subscribe.
+#[derive(Clone, Default)]
+pub struct EventBus(Arc<Mutex<Topics>>);
+
+impl Debug for EventBus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let topics = self.lock();
+        f.debug_set()
+            .entries(topics.values().map(|(name, _)| name))
+            .finish()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self { Self::default() }
+
+    fn lock(&self) -> MutexGuard<'_, Topics> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn with_topic<E: Clone + Send + 'static, T>(
+        &self,
+        f: impl FnOnce(&mut UMulticast<E>) -> T,
+    ) -> T {
+        let mut topics = self.lock();
+        let (_, topic) = topics
+            .entry(TypeId::of::<E>())
+            .or_insert_with(|| (type_name::<E>(), Box::new(UMulticast::<E>::new())));
+        f(topic.downcast_mut().expect("topic type matches its id"))
+    }
+
+    fn with_existing<E: Clone + Send + 'static, T: Default>(
+        &self,
+        f: impl FnOnce(&mut UMulticast<E>) -> T,
+    ) -> T {
+        let mut topics = self.lock();
+        let Some((_, topic)) = topics.get_mut(&TypeId::of::<E>()) else {
+            return T::default();
+        };
+        f(topic.downcast_mut().expect("topic type matches its id"))
+    }
+
+    /// Subscribes the sender to all events of type `E`.
+    pub fn subscribe<E: Clone + Send + 'static>(&self, sender: USender<E>) -> USubscription {
+        self.with_topic(|topic: &mut UMulticast<E>| topic.subscribe(sender))
+    }
+
+    /// Subscribes a service to events of type `E`, which are converted into the service message
+    /// type.
+    pub fn subscribe_into<E, M>(&self, sender: USender<M>) -> USubscription
+    where
+        E: Clone + Send + Into<M> + 'static,
+        M: Send + 'static,
+    {
+        self.with_topic(|topic: &mut UMulticast<E>| topic.subscribe_into(sender))
+    }
+
+    /// Subscribes the sender to the events of type `E` matching the `filter` predicate.
+    pub fn subscribe_filtered<E: Clone + Send + 'static>(
+        &self,
+        sender: USender<E>,
+        filter: impl Fn(&E) -> bool + Send + Sync + 'static,
+    ) -> USubscription {
+        self.with_topic(|topic: &mut UMulticast<E>| topic.subscribe_filtered(sender, filter))
+    }
+
+    pub fn unsubscribe<E: Clone + Send + 'static>(&self, subscription: USubscription) -> bool {
+        self.with_existing(|topic: &mut UMulticast<E>| topic.unsubscribe(subscription))
+    }
+
+    /// Number of subscribers to the events of type `E`.
+    pub fn subscribers<E: Clone + Send + 'static>(&self) -> usize {
+        self.with_existing(|topic: &mut UMulticast<E>| topic.len())
+    }
+
+    /// Delivers the event to all subscribers of its type. Returns the number of subscribers
+    /// which have received it.
+    ///
+    /// Blocks while bounded mailboxes of the subscribers are full. The bus is not locked while
+    /// the event is delivered, so the subscribers and other publishers are not blocked meanwhile.
+    pub fn publish<E: Clone + Send + 'static>(&self, event: E) -> usize {
+        let subscribers = self.with_existing(|topic: &mut UMulticast<E>| topic.subscribers());
+        let (count, disconnected) = UMulticast::deliver(&subscribers, event);
+        if !disconnected.is_empty() {
+            self.with_existing(|topic: &mut UMulticast<E>| {
+                for subscription in disconnected {
+                    topic.unsubscribe(subscription);
+                }
+            });
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::UProbe;
+    use crate::uservice::UMsg;
+
+    #[test]
+    fn publish_filtered_and_disconnected() {
+        let bus = EventBus::new();
+        let all = UProbe::<u32>::new();
+        let even = UProbe::<u32>::new();
+        bus.subscribe(all.sender());
+        bus.subscribe_filtered(even.sender(), |n| n % 2 == 0);
+        assert_eq!(bus.publish(1u32), 1);
+        assert_eq!(bus.publish(2u32), 2);
+        assert_eq!(bus.publish("other"), 0);
+        assert_eq!(all.drain(), vec![1, 2]);
+        assert_eq!(even.drain(), vec![2]);
+
+        drop(even);
+        assert_eq!(bus.publish(4u32), 1);
+        assert_eq!(bus.subscribers::<u32>(), 1);
+    }
+
+    #[test]
+    fn full_subscriber_blocks_only_its_publisher() {
+        let bus = EventBus::new();
+        // Subscriber with a single-message mailbox which is not being read.
+        let (sender, slow) = crossbeam_channel::bounded(1);
+        bus.subscribe(USender::<u32>(sender));
+        assert_eq!(bus.publish(1u32), 1);
+
+        let blocked_bus = bus.clone();
+        let blocked = thread::spawn(move || blocked_bus.publish(2u32));
+        thread::sleep(Duration::from_millis(20));
+        assert!(!blocked.is_finished());
+
+        let (done, finished) = crossbeam_channel::bounded(1);
+        let other_bus = bus.clone();
+        thread::spawn(move || {
+            let strings = UProbe::<&'static str>::new();
+            other_bus.subscribe(strings.sender());
+            let _ = done.send(other_bus.publish("event"));
+        });
+        assert_eq!(finished.recv_timeout(Duration::from_secs(1)), Ok(1));
+        assert_eq!(bus.subscribers::<u32>(), 1);
+
+        let recv = || match slow.recv() {
+            Ok(UMsg::Msg(n)) => Some(n),
+            _ => None,
+        };
+        assert_eq!(recv(), Some(1));
+        assert_eq!(blocked.join().ok(), Some(1));
+        assert_eq!(recv(), Some(2));
+    }
+}
@@ -0,0 +1,358 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::{KvBatch, KvStore, UMulticast, USender, USubscription};
+
+/// Event with its sequence number in the log.
+pub type LogEntry<E> = (u64, E);
+
+/// Event which can be stored in an [`EventLog`].
+pub trait LogEvent: Sized {
+    type Error: Display;
+
+    fn to_bytes(&self) -> Vec<u8>;
+    fn from_bytes(data: &[u8]) -> Result<Self, Self::Error>;
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum EventLogError<S, D> {
+    Store(S),
+    /// Event with the given sequence number can't be decoded.
+    Decode(u64, D),
+    /// Data stored under the key are not a valid part of the log.
+    Corrupted(Vec<u8>),
+}
+
+impl<S: Display, D: Display> Display for EventLogError<S, D> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EventLogError::Store(err) => write!(f, "error accessing event log storage: {err}"),
+            EventLogError::Decode(seq, err) => write!(f, "unable to decode event #{seq}: {err}"),
+            EventLogError::Corrupted(key) => write!(f, "corrupted log data under key {key:02x?}"),
+        }
+    }
+}
+
+impl<S: Display + fmt::Debug, D: Display + fmt::Debug> std::error::Error for EventLogError<S, D> {}
+
+pub type ReplayError<E, K> = EventLogError<<K as KvStore>::Error, <E as LogEvent>::Error>;
+
+/// Append-only log of service events with monotonically increasing sequence numbers, persisted
+/// in a [`KvStore`].
+///
+/// On start the service replays the log to rebuild its state; other services may tail the log
+/// by subscribing with their [`USender`]s, receiving each event after it is persisted.
+///
+/// Each appended event is committed to the store separately. [`crate::FileStore`] rewrites its
+/// whole file on each commit, so it suits only short or rarely appended logs; compact the log
+/// to keep it small.
+#[derive(Debug)]
+pub struct EventLog<E: LogEvent + Clone, K: KvStore> {
+    store: K,
+    prefix: Vec<u8>,
+    meta_key: Vec<u8>,
+    first_seq: u64,
+    next_seq: u64,
+    subscribers: UMulticast<LogEntry<E>>,
+}
+
+impl<E: LogEvent + Clone, K: KvStore> EventLog<E, K> {
+    /// Opens the log with the given `name` in the `store`, continuing the existing sequence.
+    pub fn open(store: K, name: &str) -> Result<Self, ReplayError<E, K>> {
+        let prefix = key_prefix("events", name);
+        let meta_key = format!("eventlog/{name}").into_bytes();
+        let first_seq = match store.get(&meta_key).map_err(EventLogError::Store)? {
+            None => 0,
+            Some(data) => decode_seq(&data).ok_or(EventLogError::Corrupted(meta_key.clone()))?,
+        };
+        let next_seq = match store.scan(&prefix).map_err(EventLogError::Store)?.last() {
+            None => 0,
+            Some((key, _)) => key_seq(&prefix, key)? + 1,
+        }
+        .max(first_seq);
+        Ok(Self {
+            store,
+            prefix,
+            meta_key,
+            first_seq,
+            next_seq,
+            subscribers: UMulticast::new(),
+        })
+    }
+
+    pub fn store(&self) -> &K { &self.store }
+
+    pub fn into_store(self) -> K { self.store }
+
+    /// Sequence number of the oldest event kept in the log after compactions.
+    pub fn first_seq(&self) -> u64 { self.first_seq }
+
+    /// Sequence number which will be assigned to the next appended event.
+    pub fn next_seq(&self) -> u64 { self.next_seq }
+
+    pub fn is_empty(&self) -> bool { self.first_seq == self.next_seq }
+
+    fn key(&self, seq: u64) -> Vec<u8> {
+        let mut key = self.prefix.clone();
+        key.extend(seq.to_be_bytes());
+        key
+    }
+
+    /// Persists the event and delivers it to the subscribers. Returns the event sequence number.
+    pub fn append(&mut self, event: E) -> Result<u64, K::Error> {
+        let seq = self.next_seq;
+        self.store.put(&self.key(seq), &event.to_bytes())?;
+        self.next_seq += 1;
+        self.subscribers.send((seq, event));
+        Ok(seq)
+    }
+
+    /// Reads all events starting from the sequence number `from`.
+    pub fn replay(&self, from: u64) -> Result<Vec<LogEntry<E>>, ReplayError<E, K>> {
+        let mut events = vec![];
+        for (key, data) in self
+            .store
+            .scan(&self.prefix)
+            .map_err(EventLogError::Store)?
+        {
+            let seq = key_seq(&self.prefix, &key)?;
+            if seq < from {
+                continue;
+            }
+            let event = E::from_bytes(&data).map_err(|err| EventLogError::Decode(seq, err))?;
+            events.push((seq, event));
+        }
+        Ok(events)
+    }
+
+    /// Subscribes to the events appended from now on.
+    pub fn subscribe(&mut self, subscriber: USender<LogEntry<E>>) -> USubscription
+    where E: Send + 'static {
+        self.subscribers.subscribe(subscriber)
+    }
+
+    pub fn unsubscribe(&mut self, subscription: USubscription) -> bool {
+        self.subscribers.unsubscribe(subscription)
+    }
+
+    /// Sends the subscriber all stored events starting from `from`, and subscribes it to the
+    /// events appended afterwards. Returns `None` if the subscriber has disconnected while
+    /// receiving the stored events.
+    pub fn tail(
+        &mut self,
+        from: u64,
+        subscriber: USender<LogEntry<E>>,
+    ) -> Result<Option<USubscription>, ReplayError<E, K>>
+    where
+        E: Send + 'static,
+    {
+        for item in self.replay(from)? {
+            if subscriber.send(item).is_err() {
+                return Ok(None);
+            }
+        }
+        Ok(Some(self.subscribe(subscriber)))
+    }
+
+    /// Removes all events with sequence numbers below `before`.
+    pub fn compact(&mut self, before: u64) -> Result<(), K::Error> {
+        self.compact_with(before, KvBatch::new())
+    }
+
+    /// Removes all events with sequence numbers below `before`, committing the provided batch
+    /// in the same transaction; used to save a service state snapshot replacing the removed
+    /// events.
+    pub fn compact_with(&mut self, before: u64, mut batch: KvBatch) -> Result<(), K::Error> {
+        let before = before.min(self.next_seq);
+        if before <= self.first_seq && batch.is_empty() {
+            return Ok(());
+        }
+        for seq in self.first_seq..before {
+            batch.delete(self.key(seq));
+        }
+        let first_seq = before.max(self.first_seq);
+        batch.put(self.meta_key.clone(), first_seq.to_be_bytes());
+        self.store.commit(batch)?;
+        self.first_seq = first_seq;
+        Ok(())
+    }
+}
+
+/// Prefix of the store keys of a named collection. The name length is a part of the prefix, so
+/// the prefix of one collection never starts with the prefix of another one, even if the names
+/// contain `/`.
+pub(crate) fn key_prefix(kind: &str, name: &str) -> Vec<u8> {
+    format!("{kind}/{}/{name}/", name.len()).into_bytes()
+}
+
+fn decode_seq(data: &[u8]) -> Option<u64> { data.try_into().ok().map(u64::from_be_bytes) }
+
+/// Sequence number from a key of a collection with the `prefix`, which must be followed by
+/// exactly eight bytes of the number.
+pub(crate) fn key_seq<S, D>(prefix: &[u8], key: &[u8]) -> Result<u64, EventLogError<S, D>> {
+    key.strip_prefix(prefix)
+        .and_then(decode_seq)
+        .ok_or_else(|| EventLogError::Corrupted(key.to_vec()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{MemStore, UProbe};
+
+    #[derive(Clone, Eq, PartialEq, Debug)]
+    struct Event(String);
+
+    impl LogEvent for Event {
+        type Error = String;
+
+        fn to_bytes(&self) -> Vec<u8> { self.0.as_bytes().to_vec() }
+
+        fn from_bytes(data: &[u8]) -> Result<Self, Self::Error> {
+            String::from_utf8(data.to_vec())
+                .map(Event)
+                .map_err(|err| err.to_string())
+        }
+    }
+
+    type Log = EventLog<Event, MemStore>;
+
+    fn event(s: &str) -> Event { Event(s.to_owned()) }
+
+    fn open(store: MemStore, name: &str) -> Log {
+        Log::open(store, name).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    fn append(log: &mut Log, events: &[&str]) {
+        for s in events {
+            log.append(event(s)).unwrap_or_else(|err| match err {});
+        }
+    }
+
+    fn replay(log: &Log, from: u64) -> Vec<LogEntry<Event>> {
+        log.replay(from).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    #[test]
+    fn append_replay() {
+        let mut log = open(MemStore::new(), "log");
+        assert!(log.is_empty());
+        append(&mut log, &["a", "b", "c"]);
+        assert_eq!(log.next_seq(), 3);
+        assert_eq!(replay(&log, 0), vec![(0, event("a")), (1, event("b")), (2, event("c"))]);
+        assert_eq!(replay(&log, 2), vec![(2, event("c"))]);
+        assert!(replay(&log, 3).is_empty());
+
+        let mut log = open(log.into_store(), "log");
+        assert_eq!(log.next_seq(), 3);
+        assert_eq!(log.append(event("d")), Ok(3));
+    }
+
+    #[test]
+    fn compact_and_reopen() {
+        let mut log = open(MemStore::new(), "log");
+        append(&mut log, &["a", "b", "c"]);
+        log.compact(2).unwrap_or_else(|err| match err {});
+        assert_eq!(log.first_seq(), 2);
+        assert_eq!(replay(&log, 0), vec![(2, event("c"))]);
+        // Compacting beyond the end keeps the sequence.
+        log.compact(10).unwrap_or_else(|err| match err {});
+        assert_eq!(log.first_seq(), 3);
+        assert!(log.is_empty());
+
+        let mut log = open(log.into_store(), "log");
+        assert_eq!((log.first_seq(), log.next_seq()), (3, 3));
+        append(&mut log, &["d"]);
+        assert_eq!(replay(&log, 0), vec![(3, event("d"))]);
+    }
+
+    #[test]
+    fn compact_with_snapshot() {
+        let mut log = open(MemStore::new(), "log");
+        append(&mut log, &["a", "b"]);
+        let mut batch = KvBatch::new();
+        batch.put(b"snapshot".as_slice(), b"ab".as_slice());
+        log.compact_with(2, batch)
+            .unwrap_or_else(|err| match err {});
+        assert_eq!(log.store().get(b"snapshot"), Ok(Some(b"ab".to_vec())));
+        assert!(replay(&log, 0).is_empty());
+
+        // The batch is committed even if there is nothing to compact.
+        let mut batch = KvBatch::new();
+        batch.put(b"snapshot".as_slice(), b"ab2".as_slice());
+        log.compact_with(2, batch)
+            .unwrap_or_else(|err| match err {});
+        assert_eq!(log.store().get(b"snapshot"), Ok(Some(b"ab2".to_vec())));
+        assert_eq!(open(log.into_store(), "log").first_seq(), 2);
+    }
+
+    #[test]
+    fn tail() {
+        let mut log = open(MemStore::new(), "log");
+        append(&mut log, &["a", "b"]);
+        let probe = UProbe::new();
+        let subscription = log
+            .tail(1, probe.sender())
+            .unwrap_or_else(|err| panic!("{err}"));
+        assert!(subscription.is_some());
+        append(&mut log, &["c"]);
+        assert_eq!(probe.drain(), vec![(1, event("b")), (2, event("c"))]);
+
+        let gone = UProbe::new();
+        let sender = gone.sender();
+        drop(gone);
+        assert!(
+            log.tail(0, sender)
+                .is_ok_and(|subscription| subscription.is_none())
+        );
+    }
+
+    #[test]
+    fn names_with_slash() {
+        let mut nested = open(MemStore::new(), "a/b");
+        append(&mut nested, &["nested"]);
+        let mut log = open(nested.into_store(), "a");
+        assert_eq!(log.next_seq(), 0);
+        assert!(replay(&log, 0).is_empty());
+        append(&mut log, &["a"]);
+        let nested = open(log.into_store(), "a/b");
+        assert_eq!(replay(&nested, 0), vec![(0, event("nested"))]);
+    }
+
+    #[test]
+    fn corrupted() {
+        let mut log = open(MemStore::new(), "log");
+        append(&mut log, &["a"]);
+        let mut store = log.into_store();
+        let mut key = key_prefix("events", "log");
+        key.extend([0; 9]);
+        store.put(&key, b"b").unwrap_or_else(|err| match err {});
+        assert_eq!(Log::open(store.clone(), "log").err(), Some(EventLogError::Corrupted(key)));
+
+        let mut store = MemStore::new();
+        store
+            .put(b"eventlog/log", b"short")
+            .unwrap_or_else(|err| match err {});
+        assert!(matches!(Log::open(store, "log"), Err(EventLogError::Corrupted(_))));
+    }
+}
@@ -0,0 +1,352 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::{USender, UService};
+
+/// External command run when an event with the given name occurs.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Hook {
+    pub event: String,
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    /// Time after which the command is killed.
+    pub timeout: Duration,
+}
+
+impl Hook {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn new(event: impl ToString, program: impl Into<PathBuf>) -> Self {
+        Self {
+            event: event.to_string(),
+            program: program.into(),
+            args: vec![],
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn with_args(mut self, args: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.args = args.into_iter().map(|arg| arg.to_string()).collect();
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum HookStatus {
+    Exited(ExitStatus),
+    /// The command was killed after running out of its timeout.
+    TimedOut,
+    /// The command can't be started.
+    Failed(String),
+}
+
+impl HookStatus {
+    pub fn is_success(&self) -> bool {
+        matches!(self, HookStatus::Exited(status) if status.success())
+    }
+}
+
+/// Result of running a hook command, reported back to the service which has emitted the event.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct HookReport {
+    pub event: String,
+    pub program: PathBuf,
+    pub status: HookStatus,
+    pub stdout: Vec<u8>,
+    pub elapsed: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub enum HookMsg {
+    /// Runs the hooks registered for the `event`, passing them the JSON `payload` on stdin.
+    Event {
+        event: String,
+        payload: String,
+        reply: Option<USender<HookReport>>,
+    },
+}
+
+#[derive(Debug)]
+struct HookJob {
+    hook: Hook,
+    payload: String,
+    reply: Option<USender<HookReport>>,
+}
+
+#[derive(Debug)]
+struct RunningHook {
+    job: HookJob,
+    child: Child,
+    started: Instant,
+    stdout: JoinHandle<Vec<u8>>,
+    /// Set once the command has completed, with the time it took.
+    status: Option<(HookStatus, Duration)>,
+}
+
+/// Service running external commands on events emitted by other services.
+///
+/// Commands receive the event payload on stdin and the event name in the `HOOK_EVENT` environment
+/// variable. At most `max_running` commands run at once; others wait in a queue. The service
+/// checks the running commands on each event and tick, so it must be spawned with ticks, which
+/// define how fast command completions are detected when no events arrive.
+#[derive(Debug)]
+pub struct HookRunner {
+    hooks: Vec<Hook>,
+    max_running: usize,
+    queue: VecDeque<HookJob>,
+    running: Vec<RunningHook>,
+}
+
+impl HookRunner {
+    pub fn new(hooks: impl IntoIterator<Item = Hook>, max_running: usize) -> Self {
+        Self {
+            hooks: hooks.into_iter().collect(),
+            max_running: max_running.max(1),
+            queue: VecDeque::new(),
+            running: vec![],
+        }
+    }
+
+    pub fn hooks(&self) -> &[Hook] { &self.hooks }
+
+    /// Number of commands which are running or waiting to be run.
+    pub fn pending(&self) -> usize { self.queue.len() + self.running.len() }
+
+    fn spawn(job: &HookJob) -> io::Result<(Child, JoinHandle<Vec<u8>>)> {
+        let mut child = Command::new(&job.hook.program)
+            .args(&job.hook.args)
+            .env("HOOK_EVENT", &job.hook.event)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        // Payload is written and output is read from separate threads, so that a command which
+        // does not read its input or produces large output can't block the service.
+        if let Some(mut stdin) = child.stdin.take() {
+            let payload = job.payload.clone();
+            thread::spawn(move || stdin.write_all(payload.as_bytes()));
+        }
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let reader = thread::spawn(move || {
+            let mut buf = vec![];
+            let _ = stdout.read_to_end(&mut buf);
+            buf
+        });
+        Ok((child, reader))
+    }
+
+    fn report(&self, job: HookJob, status: HookStatus, stdout: Vec<u8>, elapsed: Duration) {
+        let context = format!("hook {} for event {}", job.hook.program.display(), job.hook.event);
+        match &status {
+            HookStatus::Exited(status) if !status.success() => self.error(&context, status),
+            HookStatus::Exited(_) => {}
+            HookStatus::TimedOut => self.error(&context, "timed out"),
+            HookStatus::Failed(err) => self.error(&context, err),
+        }
+        let Some(reply) = job.reply else {
+            return;
+        };
+        let report = HookReport {
+            event: job.hook.event,
+            program: job.hook.program,
+            status,
+            stdout,
+            elapsed,
+        };
+        if reply.send(report).is_err() {
+            #[cfg(feature = "log")]
+            log::debug!(target: Self::NAME, "hook report receiver has disconnected");
+        }
+    }
+
+    fn start_queued(&mut self) {
+        while self.running.len() < self.max_running {
+            let Some(job) = self.queue.pop_front() else {
+                break;
+            };
+            #[cfg(feature = "log")]
+            log::debug!(
+                target: Self::NAME,
+                "running hook {} for event {}",
+                job.hook.program.display(),
+                job.hook.event
+            );
+            match Self::spawn(&job) {
+                Ok((child, stdout)) => self.running.push(RunningHook {
+                    job,
+                    child,
+                    started: Instant::now(),
+                    stdout,
+                    status: None,
+                }),
+                Err(err) => {
+                    self.report(job, HookStatus::Failed(err.to_string()), vec![], Duration::ZERO)
+                }
+            }
+        }
+    }
+
+    fn poll_running(&mut self) {
+        let mut idx = 0;
+        while idx < self.running.len() {
+            let running = &mut self.running[idx];
+            let elapsed = running.started.elapsed();
+            let timed_out = elapsed >= running.job.hook.timeout;
+            if running.status.is_none() {
+                running.status = match running.child.try_wait() {
+                    Ok(Some(status)) => Some((HookStatus::Exited(status), elapsed)),
+                    Ok(None) if timed_out => {
+                        let _ = running.child.kill();
+                        let _ = running.child.wait();
+                        Some((HookStatus::TimedOut, elapsed))
+                    }
+                    Ok(None) => None,
+                    Err(err) => Some((HookStatus::Failed(err.to_string()), elapsed)),
+                };
+            }
+            // The output pipe may be held open by processes spawned by the command, so the output
+            // is awaited only until the command timeout.
+            if running.status.is_none() || !(running.stdout.is_finished() || timed_out) {
+                idx += 1;
+                continue;
+            }
+            let running = self.running.swap_remove(idx);
+            let (status, elapsed) = running.status.expect("checked above");
+            let stdout = if running.stdout.is_finished() {
+                running.stdout.join().unwrap_or_default()
+            } else {
+                vec![]
+            };
+            self.report(running.job, status, stdout, elapsed);
+        }
+    }
+}
+
+impl UService for HookRunner {
+    type Msg = HookMsg;
+    type Error = io::Error;
+    const NAME: &'static str = "hooks";
+
+    fn tick(&mut self) -> Result<(), Self::Error> {
+        self.poll_running();
+        self.start_queued();
+        Ok(())
+    }
+
+    fn process(&mut self, msg: Self::Msg) -> Result<ControlFlow<u8>, Self::Error> {
+        // Ticks don't happen while events keep arriving, so the commands are checked here too.
+        self.poll_running();
+        match msg {
+            HookMsg::Event { event, payload, reply } => {
+                let jobs = self
+                    .hooks
+                    .iter()
+                    .filter(|hook| hook.event == event)
+                    .map(|hook| HookJob {
+                        hook: hook.clone(),
+                        payload: payload.clone(),
+                        reply: reply.clone(),
+                    });
+                self.queue.extend(jobs);
+                self.start_queued();
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn terminate(&mut self) {
+        self.queue.clear();
+        for mut running in self.running.drain(..) {
+            let _ = running.child.kill();
+            let _ = running.child.wait();
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use crate::{TestExecutor, UProbe};
+
+    fn event(probe: &UProbe<HookReport>) -> HookMsg {
+        HookMsg::Event {
+            event: "ev".to_owned(),
+            payload: "{}".to_owned(),
+            reply: Some(probe.sender()),
+        }
+    }
+
+    #[test]
+    fn reaped_without_ticks() {
+        let hook = Hook::new("ev", "sh").with_args(["-c", "cat"]);
+        let mut executor = TestExecutor::new(HookRunner::new([hook], 1), None);
+        let probe = UProbe::new();
+        executor.deliver(event(&probe));
+        executor.deliver(event(&probe));
+        assert_eq!(executor.service().pending(), 2);
+        for _ in 0..2 {
+            thread::sleep(Duration::from_millis(200));
+            executor.deliver(HookMsg::Event {
+                event: "other".to_owned(),
+                payload: String::new(),
+                reply: None,
+            });
+        }
+        assert_eq!(executor.service().pending(), 0);
+        let reports = probe.drain();
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(|report| report.status.is_success()));
+        assert_eq!(reports[0].stdout, b"{}");
+    }
+
+    #[test]
+    fn timeout_enforced_without_ticks() {
+        let hook = Hook::new("ev", "sleep")
+            .with_args(["5"])
+            .with_timeout(Duration::from_millis(50));
+        let mut executor = TestExecutor::new(HookRunner::new([hook], 1), None);
+        let probe = UProbe::new();
+        executor.deliver(event(&probe));
+        thread::sleep(Duration::from_millis(100));
+        executor.deliver(HookMsg::Event {
+            event: "other".to_owned(),
+            payload: String::new(),
+            reply: None,
+        });
+        let reports = probe.drain();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].status, HookStatus::TimedOut);
+        assert_eq!(executor.service().pending(), 0);
+    }
+}
@@ -23,6 +23,58 @@
 
 mod uservice;
 mod uthread;
+mod uexecutor;
+mod umulticast;
+mod eventbus;
+mod pipeline;
+mod registry;
+mod hooks;
+mod monitor;
+mod watchdog;
+mod uclock;
+mod scheduler;
+mod taskqueue;
+#[cfg(feature = "store")]
+mod store;
+#[cfg(feature = "store")]
+mod eventlog;
+#[cfg(feature = "store")]
+mod outbox;
+#[cfg(feature = "bench")]
+mod ustats;
+#[cfg(feature = "plugin")]
+mod plugin;
 
-pub use uservice::{UError, UErrorMsg, UErrorSender, UResponder, UResult, USender, UService};
-pub use uthread::UThread;
+pub use eventbus::EventBus;
+#[cfg(feature = "store")]
+pub use eventlog::{EventLog, EventLogError, LogEntry, LogEvent, ReplayError};
+pub use hooks::{Hook, HookMsg, HookReport, HookRunner, HookStatus};
+#[cfg(feature = "log")]
+pub use monitor::LogSink;
+pub use monitor::{AlertSink, Monitor};
+#[cfg(feature = "store")]
+pub use outbox::{Outbox, OutboxMsg, OutboxRelay, Relayed};
+pub use pipeline::{Pipeline, PipelineBuilder, UStage};
+#[cfg(feature = "plugin")]
+pub use plugin::{
+    PLUGIN_ABI_VERSION, PLUGIN_CRATE_VERSION, PLUGIN_RUSTC_VERSION, PLUGIN_SYMBOL, PluginDecl,
+    PluginEntry, PluginError, PluginHost, PluginRegistrar,
+};
+pub use registry::{DynUThread, RegistryError, ServiceRegistry};
+#[cfg(feature = "store")]
+pub use scheduler::KvJobStore;
+pub use scheduler::{Cron, CronError, Job, JobStore, Schedule, Scheduler, SchedulerMsg};
+#[cfg(feature = "store")]
+pub use store::{FileStore, KvBatch, KvOp, KvPairs, KvStore, MemStore, SharedStore, USnapshot};
+pub use taskqueue::{Task, TaskClient, TaskMsg, TaskQueue};
+pub use uclock::{Clock, MockClock, SystemClock};
+pub use uexecutor::{TestExecutor, UProbe};
+pub use umulticast::{UFilter, UMulticast, USubscription};
+pub use uservice::{
+    UError, UErrorMsg, UErrorSender, UFailure, UFailureKind, UResponder, UResult, USender,
+    UService, USeverity,
+};
+#[cfg(feature = "bench")]
+pub use ustats::UStats;
+pub use uthread::{DrainError, ThreadConfig, UThread};
+pub use watchdog::{UHeartbeat, Watchdog, WatchdogMsg};
@@ -0,0 +1,313 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::{self, Debug, Formatter};
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+use crate::{Clock, SystemClock, UErrorMsg, USender, UService, USeverity};
+
+/// Destination for the reports aggregated by the [`Monitor`], such as logs, metrics or alerting.
+pub trait AlertSink: Send {
+    fn alert(&mut self, report: &UErrorMsg);
+}
+
+impl<F: FnMut(&UErrorMsg) + Send> AlertSink for F {
+    fn alert(&mut self, report: &UErrorMsg) { self(report) }
+}
+
+/// Forwards reports to another service.
+impl AlertSink for USender<UErrorMsg> {
+    fn alert(&mut self, report: &UErrorMsg) { let _ = self.send(report.clone()); }
+}
+
+/// Writes reports to the log, under the `monitor` target.
+#[cfg(feature = "log")]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct LogSink;
+
+#[cfg(feature = "log")]
+impl AlertSink for LogSink {
+    fn alert(&mut self, report: &UErrorMsg) {
+        let level = match report.severity {
+            USeverity::Info => log::Level::Info,
+            USeverity::Warn => log::Level::Warn,
+            USeverity::Error | USeverity::Fatal => log::Level::Error,
+        };
+        log::log!(target: Monitor::NAME, level, "{report}");
+    }
+}
+
+struct Sink {
+    min_severity: USeverity,
+    sink: Box<dyn AlertSink>,
+}
+
+#[derive(Debug)]
+struct RateWindow {
+    started: Instant,
+    count: usize,
+    suppressed: usize,
+}
+
+/// Service aggregating reports from other services, which use its sender as their
+/// [`UService::monitor`], and forwarding them to the alert sinks.
+///
+/// With a rate limit set, reports of a service exceeding the limit within the limit period are
+/// dropped, except for the fatal ones; the number of dropped reports is sent to the sinks as a
+/// warning once the period ends. Spawn the monitor with ticks for such warnings to be sent
+/// without waiting for further reports.
+pub struct Monitor {
+    clock: Box<dyn Clock>,
+    sinks: Vec<Sink>,
+    rate_limit: Option<(usize, Duration)>,
+    windows: HashMap<String, RateWindow>,
+}
+
+impl Debug for Monitor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Monitor")
+            .field("sinks", &self.sinks.len())
+            .field("rate_limit", &self.rate_limit)
+            .field("windows", &self.windows)
+            .finish()
+    }
+}
+
+impl Default for Monitor {
+    fn default() -> Self { Self::new() }
+}
+
+impl Monitor {
+    pub fn new() -> Self { Self::with_clock(SystemClock) }
+
+    /// Creates monitor measuring rate limit periods with the `clock`.
+    pub fn with_clock(clock: impl Clock) -> Self {
+        Self {
+            clock: Box::new(clock),
+            sinks: vec![],
+            rate_limit: None,
+            windows: HashMap::new(),
+        }
+    }
+
+    /// Adds the sink receiving reports with severity of at least `min_severity`.
+    pub fn with_sink(mut self, min_severity: USeverity, sink: impl AlertSink + 'static) -> Self {
+        self.sinks.push(Sink { min_severity, sink: Box::new(sink) });
+        self
+    }
+
+    /// Limits each service to `max_reports` reports within each `period`.
+    pub fn with_rate_limit(mut self, max_reports: usize, period: Duration) -> Self {
+        self.rate_limit = Some((max_reports, period));
+        self
+    }
+
+    fn dispatch(&mut self, report: &UErrorMsg) {
+        for sink in &mut self.sinks {
+            if report.severity >= sink.min_severity {
+                sink.sink.alert(report);
+            }
+        }
+    }
+
+    /// Reports the number of suppressed reports for the services whose rate limit periods have
+    /// ended, or for all services if `all` is set.
+    fn flush_suppressed(&mut self, all: bool) {
+        let Some((_, period)) = self.rate_limit else {
+            return;
+        };
+        let now = self.clock.now();
+        let mut reports = vec![];
+        self.windows.retain(|service, window| {
+            if !all && now.duration_since(window.started) < period {
+                return true;
+            }
+            if window.suppressed > 0 {
+                let msg = format!("{} report(s) suppressed by the rate limit", window.suppressed);
+                reports.push(
+                    UErrorMsg::new(service, USeverity::Warn, msg)
+                        .with_field("suppressed", window.suppressed),
+                );
+            }
+            false
+        });
+        for report in reports {
+            self.dispatch(&report);
+        }
+    }
+
+    /// Checks the report against the service rate limit, returning whether it can be sent.
+    fn admit(&mut self, report: &UErrorMsg) -> bool {
+        let Some((max_reports, _)) = self.rate_limit else {
+            return true;
+        };
+        if report.severity == USeverity::Fatal {
+            return true;
+        }
+        let now = self.clock.now();
+        let window = self
+            .windows
+            .entry(report.service.clone())
+            .or_insert(RateWindow { started: now, count: 0, suppressed: 0 });
+        if window.count < max_reports {
+            window.count += 1;
+            true
+        } else {
+            window.suppressed += 1;
+            false
+        }
+    }
+}
+
+impl UService for Monitor {
+    type Msg = UErrorMsg;
+    type Error = Infallible;
+    const NAME: &'static str = "monitor";
+
+    fn tick(&mut self) -> Result<(), Self::Error> {
+        self.flush_suppressed(false);
+        Ok(())
+    }
+
+    fn process(&mut self, msg: Self::Msg) -> Result<ControlFlow<u8>, Self::Error> {
+        self.flush_suppressed(false);
+        if self.admit(&msg) {
+            self.dispatch(&msg);
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn terminate(&mut self) { self.flush_suppressed(true); }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{MockClock, TestExecutor, UProbe};
+
+    fn report(service: &str, severity: USeverity) -> UErrorMsg {
+        UErrorMsg::new(service, severity, "failure")
+    }
+
+    fn monitor(probe: &UProbe<UErrorMsg>) -> TestExecutor<Monitor> {
+        let clock = MockClock::new();
+        let monitor = Monitor::with_clock(clock.clone())
+            .with_sink(USeverity::Info, probe.sender())
+            .with_rate_limit(2, Duration::from_secs(10));
+        TestExecutor::with_clock(monitor, Some(Duration::from_secs(1)), clock)
+    }
+
+    fn services(reports: &[UErrorMsg]) -> Vec<&str> {
+        reports
+            .iter()
+            .map(|report| report.service.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn rate_window_per_service() {
+        let probe = UProbe::new();
+        let mut executor = monitor(&probe);
+        for service in ["a", "a", "a", "b", "a"] {
+            executor.deliver(report(service, USeverity::Error));
+        }
+        assert_eq!(services(&probe.drain()), vec!["a", "a", "b"]);
+
+        executor.advance(Duration::from_secs(9));
+        assert!(probe.is_empty());
+        executor.advance(Duration::from_secs(1));
+        let reports = probe.drain();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].service, "a");
+        assert_eq!(reports[0].severity, USeverity::Warn);
+        assert_eq!(reports[0].field("suppressed"), Some("2"));
+
+        // A new period starts with the next report.
+        executor.deliver(report("a", USeverity::Error));
+        assert_eq!(services(&probe.drain()), vec!["a"]);
+    }
+
+    #[test]
+    fn suppressed_reported_on_terminate() {
+        let probe = UProbe::new();
+        let mut executor = monitor(&probe);
+        for _ in 0..3 {
+            executor.deliver(report("a", USeverity::Error));
+        }
+        executor.deliver(report("b", USeverity::Error));
+        assert_eq!(probe.drain().len(), 3);
+        executor.terminate();
+        let reports = probe.drain();
+        assert_eq!(services(&reports), vec!["a"]);
+        assert_eq!(reports[0].field("suppressed"), Some("1"));
+    }
+
+    #[test]
+    fn fatal_bypasses_limit() {
+        let probe = UProbe::new();
+        let mut executor = monitor(&probe);
+        for _ in 0..2 {
+            executor.deliver(report("a", USeverity::Error));
+        }
+        executor.deliver(report("a", USeverity::Fatal));
+        executor.deliver(report("a", USeverity::Error));
+        let severities = probe
+            .drain()
+            .into_iter()
+            .map(|report| report.severity)
+            .collect::<Vec<_>>();
+        assert_eq!(severities, vec![USeverity::Error, USeverity::Error, USeverity::Fatal]);
+    }
+
+    #[test]
+    fn min_severity() {
+        let all = UProbe::new();
+        let errors = UProbe::new();
+        let monitor = Monitor::new()
+            .with_sink(USeverity::Info, all.sender())
+            .with_sink(USeverity::Error, errors.sender());
+        let mut executor = TestExecutor::new(monitor, None);
+        for severity in [USeverity::Info, USeverity::Warn, USeverity::Error, USeverity::Fatal] {
+            executor.deliver(report("a", severity));
+        }
+        assert_eq!(all.len(), 4);
+        let severities = errors
+            .drain()
+            .into_iter()
+            .map(|report| report.severity)
+            .collect::<Vec<_>>();
+        assert_eq!(severities, vec![USeverity::Error, USeverity::Fatal]);
+    }
+
+    #[test]
+    fn closure_sink() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let monitor = Monitor::new().with_sink(USeverity::Warn, move |report: &UErrorMsg| {
+            let _ = sender.send(report.to_string());
+        });
+        let mut executor = TestExecutor::new(monitor, None);
+        executor.deliver(report("a", USeverity::Warn).with_field("attempt", 3));
+        assert_eq!(receiver.try_recv().ok().as_deref(), Some("Warning in a: failure attempt=3"));
+    }
+}
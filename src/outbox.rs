@@ -0,0 +1,392 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::marker::PhantomData;
+use std::ops::ControlFlow;
+
+use crate::eventlog::{key_prefix, key_seq};
+use crate::{KvBatch, KvStore, LogEntry, LogEvent, ReplayError, SharedStore, USender, UService};
+
+/// Messages which a service has committed to send, stored together with the service state.
+///
+/// A service commits its state changes and the messages caused by them in a single
+/// [`KvStore`] transaction with [`Outbox::commit`]; the messages are removed from the store only
+/// after they are delivered by [`Outbox::relay`] or [`OutboxRelay`]. This way a crash between
+/// updating the state and sending a notification does not lose the notification; instead, the
+/// message may be delivered more than once.
+///
+/// Messages which can't be decoded are moved by the relay to the dead letters, so they don't
+/// hold back the messages committed after them.
+#[derive(Debug)]
+pub struct Outbox<M: LogEvent, K: KvStore> {
+    store: K,
+    prefix: Vec<u8>,
+    dead_prefix: Vec<u8>,
+    next_id: u64,
+    _phantom: PhantomData<M>,
+}
+
+/// Outcome of [`Outbox::relay`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Relayed<E> {
+    /// Number of delivered messages.
+    pub sent: usize,
+    /// Messages which can't be decoded and are moved to the dead letters, with the errors.
+    pub dead: Vec<(u64, E)>,
+}
+
+impl<M: LogEvent, K: KvStore> Outbox<M, K> {
+    pub fn open(store: K, name: &str) -> Result<Self, ReplayError<M, K>> {
+        let mut outbox = Self {
+            store,
+            prefix: key_prefix("outbox", name),
+            dead_prefix: key_prefix("outbox-dead", name),
+            next_id: 0,
+            _phantom: PhantomData,
+        };
+        // Dead letters keep their ids, which must not be reused.
+        for prefix in [&outbox.prefix, &outbox.dead_prefix] {
+            if let Some((id, _)) = outbox.scan(prefix)?.last() {
+                outbox.next_id = outbox.next_id.max(id + 1);
+            }
+        }
+        Ok(outbox)
+    }
+
+    pub fn store(&self) -> &K { &self.store }
+
+    pub fn store_mut(&mut self) -> &mut K { &mut self.store }
+
+    fn key(&self, id: u64) -> Vec<u8> {
+        let mut key = self.prefix.clone();
+        key.extend(id.to_be_bytes());
+        key
+    }
+
+    fn dead_key(&self, id: u64) -> Vec<u8> {
+        let mut key = self.dead_prefix.clone();
+        key.extend(id.to_be_bytes());
+        key
+    }
+
+    fn scan(&self, prefix: &[u8]) -> Result<Vec<LogEntry<Vec<u8>>>, ReplayError<M, K>> {
+        self.store
+            .scan(prefix)
+            .map_err(ReplayError::<M, K>::Store)?
+            .into_iter()
+            .map(|(key, data)| Ok((key_seq(prefix, &key)?, data)))
+            .collect()
+    }
+
+    /// Atomically commits the state changes from the `batch` together with the outgoing
+    /// `messages`.
+    pub fn commit(
+        &mut self,
+        mut batch: KvBatch,
+        messages: impl IntoIterator<Item = M>,
+    ) -> Result<(), K::Error> {
+        let mut next_id = self.next_id;
+        for msg in messages {
+            batch.put(self.key(next_id), msg.to_bytes());
+            next_id += 1;
+        }
+        self.store.commit(batch)?;
+        self.next_id = next_id;
+        Ok(())
+    }
+
+    /// Messages committed but not yet delivered, in the order of their commit. Messages which
+    /// can't be decoded are skipped.
+    pub fn pending(&self) -> Result<Vec<LogEntry<M>>, ReplayError<M, K>> {
+        Ok(self
+            .scan(&self.prefix)?
+            .into_iter()
+            .filter_map(|(id, data)| M::from_bytes(&data).ok().map(|msg| (id, msg)))
+            .collect())
+    }
+
+    /// Encoded messages which were moved to the dead letters since they can't be decoded.
+    pub fn dead_letters(&self) -> Result<Vec<LogEntry<Vec<u8>>>, ReplayError<M, K>> {
+        self.scan(&self.dead_prefix)
+    }
+
+    /// Removes delivered messages from the outbox.
+    pub fn mark_sent(&mut self, ids: impl IntoIterator<Item = u64>) -> Result<(), K::Error> {
+        let mut batch = KvBatch::new();
+        for id in ids {
+            batch.delete(self.key(id));
+        }
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.store.commit(batch)
+    }
+
+    /// Sends pending messages to the `target` in order, removing the sent ones from the outbox
+    /// and moving the ones which can't be decoded to the dead letters. Stops at the first message
+    /// which can't be sent.
+    pub fn relay(&mut self, target: &USender<M>) -> Result<Relayed<M::Error>, ReplayError<M, K>> {
+        let mut batch = KvBatch::new();
+        let mut relayed = Relayed { sent: 0, dead: vec![] };
+        for (id, data) in self.scan(&self.prefix)? {
+            match M::from_bytes(&data) {
+                Err(err) => {
+                    batch.delete(self.key(id)).put(self.dead_key(id), data);
+                    relayed.dead.push((id, err));
+                }
+                Ok(msg) => {
+                    if target.send(msg).is_err() {
+                        break;
+                    }
+                    batch.delete(self.key(id));
+                    relayed.sent += 1;
+                }
+            }
+        }
+        if !batch.is_empty() {
+            self.store
+                .commit(batch)
+                .map_err(ReplayError::<M, K>::Store)?;
+        }
+        Ok(relayed)
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum OutboxMsg {
+    /// Deliver the pending messages without waiting for the next tick.
+    Flush,
+}
+
+/// Service delivering messages committed to an [`Outbox`] sharing the same store.
+///
+/// Delivers pending messages on each tick and on [`OutboxMsg::Flush`] requests, which services
+/// may send after committing to the outbox. Messages moved to the dead letters are reported as
+/// errors.
+pub struct OutboxRelay<M: LogEvent, K: KvStore> {
+    outbox: Outbox<M, SharedStore<K>>,
+    target: USender<M>,
+}
+
+impl<M: LogEvent, K: KvStore> OutboxRelay<M, K> {
+    pub fn new(
+        store: SharedStore<K>,
+        name: &str,
+        target: USender<M>,
+    ) -> Result<Self, ReplayError<M, K>> {
+        Ok(Self { outbox: Outbox::open(store, name)?, target })
+    }
+
+    pub fn outbox(&self) -> &Outbox<M, SharedStore<K>> { &self.outbox }
+}
+
+impl<M, K> OutboxRelay<M, K>
+where
+    M: LogEvent + Send + 'static,
+    M::Error: Send + Sync,
+    K: KvStore + 'static,
+    K::Error: Sync,
+{
+    fn relay(&mut self) -> Result<(), ReplayError<M, K>> {
+        let relayed = self.outbox.relay(&self.target)?;
+        for (id, err) in relayed.dead {
+            let context =
+                format!("outbox message #{id} can't be decoded and is moved to dead letters");
+            self.error(&context, err);
+        }
+        Ok(())
+    }
+}
+
+impl<M, K> UService for OutboxRelay<M, K>
+where
+    M: LogEvent + Send + 'static,
+    M::Error: Send + Sync,
+    K: KvStore + 'static,
+    K::Error: Sync,
+{
+    type Msg = OutboxMsg;
+    type Error = ReplayError<M, K>;
+    const NAME: &'static str = "outbox";
+
+    fn tick(&mut self) -> Result<(), Self::Error> { self.relay() }
+
+    fn process(&mut self, msg: Self::Msg) -> Result<ControlFlow<u8>, Self::Error> {
+        match msg {
+            OutboxMsg::Flush => self.relay()?,
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn terminate(&mut self) {
+        if let Err(err) = self.relay() {
+            self.error("unable to deliver outbox messages on termination", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{MemStore, TestExecutor, UProbe};
+
+    #[derive(Clone, Eq, PartialEq, Debug)]
+    struct Msg(Vec<u8>);
+
+    impl LogEvent for Msg {
+        type Error = String;
+
+        fn to_bytes(&self) -> Vec<u8> { self.0.clone() }
+
+        fn from_bytes(data: &[u8]) -> Result<Self, Self::Error> {
+            match data.first() {
+                Some(0xFF) => Err("invalid message".to_owned()),
+                _ => Ok(Msg(data.to_vec())),
+            }
+        }
+    }
+
+    fn msg(s: &str) -> Msg { Msg(s.as_bytes().to_vec()) }
+
+    fn open(store: MemStore, name: &str) -> Outbox<Msg, MemStore> {
+        Outbox::open(store, name).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    fn commit(outbox: &mut Outbox<Msg, MemStore>, batch: KvBatch, msgs: &[Msg]) {
+        outbox
+            .commit(batch, msgs.iter().cloned())
+            .unwrap_or_else(|err| match err {});
+    }
+
+    fn ids(outbox: &Outbox<Msg, MemStore>) -> Vec<u64> {
+        let pending = outbox.pending().unwrap_or_else(|err| panic!("{err}"));
+        pending.into_iter().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn commit_relay_reopen() {
+        let mut outbox = open(MemStore::new(), "outbox");
+        let mut batch = KvBatch::new();
+        batch.put(b"state".as_slice(), b"1".as_slice());
+        commit(&mut outbox, batch, &[msg("a"), msg("b")]);
+        assert_eq!(outbox.store().get(b"state"), Ok(Some(b"1".to_vec())));
+
+        let mut outbox = open(outbox.store().clone(), "outbox");
+        commit(&mut outbox, KvBatch::new(), &[msg("c")]);
+        assert_eq!(ids(&outbox), vec![0, 1, 2]);
+
+        let probe = UProbe::new();
+        let relayed = outbox.relay(&probe.sender());
+        assert_eq!(relayed, Ok(Relayed { sent: 3, dead: vec![] }));
+        assert_eq!(probe.drain(), vec![msg("a"), msg("b"), msg("c")]);
+        assert!(ids(&outbox).is_empty());
+        assert!(ids(&open(outbox.store().clone(), "outbox")).is_empty());
+    }
+
+    #[test]
+    fn crash_before_mark_sent() {
+        let mut outbox = open(MemStore::new(), "outbox");
+        commit(&mut outbox, KvBatch::new(), &[msg("a"), msg("b")]);
+        let probe = UProbe::new();
+        let sender = probe.sender();
+        for (_, msg) in outbox.pending().unwrap_or_else(|err| panic!("{err}")) {
+            let _ = sender.send(msg);
+        }
+        // The service crashes before marking the messages as sent, so they are sent again.
+        let mut outbox = open(outbox.store().clone(), "outbox");
+        assert_eq!(outbox.relay(&sender).map(|relayed| relayed.sent), Ok(2));
+        assert_eq!(probe.drain(), vec![msg("a"), msg("b"), msg("a"), msg("b")]);
+
+        commit(&mut outbox, KvBatch::new(), &[msg("c"), msg("d")]);
+        outbox.mark_sent([2]).unwrap_or_else(|err| match err {});
+        assert_eq!(ids(&outbox), vec![3]);
+    }
+
+    #[test]
+    fn disconnected_target() {
+        let mut outbox = open(MemStore::new(), "outbox");
+        commit(&mut outbox, KvBatch::new(), &[msg("a")]);
+        let sender = UProbe::new().sender();
+        assert_eq!(outbox.relay(&sender), Ok(Relayed { sent: 0, dead: vec![] }));
+        assert_eq!(ids(&outbox), vec![0]);
+    }
+
+    #[test]
+    fn dead_letters() {
+        let mut outbox = open(MemStore::new(), "outbox");
+        commit(&mut outbox, KvBatch::new(), &[msg("a"), Msg(vec![0xFF]), msg("b")]);
+        assert_eq!(ids(&outbox), vec![0, 2]);
+
+        let probe = UProbe::new();
+        let relayed = outbox.relay(&probe.sender());
+        let dead = vec![(1, "invalid message".to_owned())];
+        assert_eq!(relayed, Ok(Relayed { sent: 2, dead }));
+        assert_eq!(probe.drain(), vec![msg("a"), msg("b")]);
+        assert_eq!(outbox.dead_letters(), Ok(vec![(1, vec![0xFF])]));
+
+        // Ids of the dead letters are not reused.
+        let mut outbox = open(outbox.store().clone(), "outbox");
+        commit(&mut outbox, KvBatch::new(), &[msg("c")]);
+        assert_eq!(ids(&outbox), vec![2]);
+    }
+
+    #[test]
+    fn names_with_slash() {
+        let mut nested = open(MemStore::new(), "a/b");
+        commit(&mut nested, KvBatch::new(), &[msg("nested")]);
+        let outbox = open(nested.store().clone(), "a");
+        assert!(ids(&outbox).is_empty());
+    }
+
+    #[test]
+    fn relay_service() {
+        let store = SharedStore::new(MemStore::new());
+        let probe = UProbe::new();
+        let relay = OutboxRelay::<Msg, _>::new(store.clone(), "outbox", probe.sender())
+            .unwrap_or_else(|err| panic!("{err}"));
+        let mut executor = TestExecutor::new(relay, Some(Duration::from_secs(1)));
+        let mut outbox =
+            Outbox::<Msg, _>::open(store, "outbox").unwrap_or_else(|err| panic!("{err}"));
+
+        outbox
+            .commit(KvBatch::new(), [Msg(vec![0xFF]), msg("a")])
+            .unwrap_or_else(|err| match err {});
+        executor.deliver(OutboxMsg::Flush);
+        assert_eq!(probe.drain(), vec![msg("a")]);
+        assert_eq!(
+            executor
+                .service()
+                .outbox()
+                .dead_letters()
+                .map(|dead| dead.len()),
+            Ok(1)
+        );
+
+        outbox
+            .commit(KvBatch::new(), [msg("b")])
+            .unwrap_or_else(|err| match err {});
+        assert_eq!(executor.advance(Duration::from_secs(1)), 1);
+        assert_eq!(probe.drain(), vec![msg("b")]);
+        assert!(executor.errors().is_empty());
+    }
+}
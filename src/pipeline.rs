@@ -0,0 +1,373 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+use std::time::Duration;
+
+use crate::{DrainError, DynUThread, ThreadConfig, USender, UService, UThread};
+
+/// Service which is a processing stage of a [`Pipeline`], forwarding its output to the next
+/// stage.
+pub trait UStage: UService {
+    type Output: Send;
+
+    /// Provides the stage with the sender to the next pipeline stage.
+    fn connect(&mut self, next: USender<Self::Output>);
+}
+
+type Stages = Vec<DynUThread>;
+type Spawner<In, Out> = Box<dyn FnOnce(USender<Out>, &mut Stages) -> io::Result<USender<In>>>;
+
+/// Builder connecting services into a [`Pipeline`], from the first stage receiving messages of
+/// type `In` to the stage producing `Out`.
+pub struct PipelineBuilder<In, Out> {
+    spawner: Spawner<In, Out>,
+    config: ThreadConfig,
+}
+
+impl<In: Send + 'static> Default for PipelineBuilder<In, In> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<In: Send + 'static> PipelineBuilder<In, In> {
+    pub fn new() -> Self {
+        Self {
+            spawner: Box::new(|sender, _| Ok(sender)),
+            config: ThreadConfig::default(),
+        }
+    }
+
+    /// Creates builder using the `capacity` as the default buffer size between stages.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut builder = Self::new();
+        builder.config.capacity = Some(capacity);
+        builder
+    }
+}
+
+impl<In: Send + 'static, Out: Send + 'static> PipelineBuilder<In, Out> {
+    /// Adds the next processing stage, with the default buffer size.
+    pub fn then<S: UStage<Msg = Out>>(self, stage: S) -> PipelineBuilder<In, S::Output> {
+        let config = self.config.clone();
+        self.then_with(stage, None, config)
+    }
+
+    /// Adds the next processing stage, running with the given ticks and thread configuration.
+    pub fn then_with<S: UStage<Msg = Out>>(
+        self,
+        mut stage: S,
+        ticks: Option<Duration>,
+        config: ThreadConfig,
+    ) -> PipelineBuilder<In, S::Output> {
+        let prev = self.spawner;
+        let spawner = Box::new(move |next: USender<S::Output>, stages: &mut Stages| {
+            stage.connect(next);
+            let thread = UThread::with_config(stage, ticks, config)?;
+            let sender = thread.sender();
+            stages.push(thread.into());
+            prev(sender, stages)
+        });
+        PipelineBuilder { spawner, config: self.config }
+    }
+
+    /// Completes the pipeline with the final stage and starts all the stages.
+    pub fn finish<S: UService<Msg = Out>>(self, sink: S) -> io::Result<Pipeline<In>> {
+        let config = self.config.clone();
+        self.finish_with(sink, None, config)
+    }
+
+    /// Completes the pipeline with the final stage, running with the given ticks and thread
+    /// configuration, and starts all the stages.
+    pub fn finish_with<S: UService<Msg = Out>>(
+        self,
+        sink: S,
+        ticks: Option<Duration>,
+        config: ThreadConfig,
+    ) -> io::Result<Pipeline<In>> {
+        let sink = UThread::with_config(sink, ticks, config)?;
+        let sender = sink.sender();
+        let mut stages: Stages = vec![sink.into()];
+        let head = (self.spawner)(sender, &mut stages)?;
+        stages.reverse();
+        Ok(Pipeline { head, stages })
+    }
+}
+
+/// Chain of services, each running in its own thread and forwarding its output to the next one.
+///
+/// With bounded stage buffers, a slow stage blocks the previous ones, down to the senders to
+/// the pipeline. Dropping the pipeline shuts it down.
+pub struct Pipeline<In> {
+    head: USender<In>,
+    /// Stage threads, starting from the pipeline head.
+    stages: Stages,
+}
+
+impl<In> Pipeline<In> {
+    pub fn builder() -> PipelineBuilder<In, In>
+    where In: Send + 'static {
+        PipelineBuilder::new()
+    }
+
+    /// Sender to the first pipeline stage.
+    pub fn sender(&self) -> USender<In> { self.head.clone() }
+
+    /// Names of the stage services, starting from the pipeline head.
+    pub fn stages(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.stages.iter().map(|stage| stage.name())
+    }
+
+    /// Stops the stages one by one starting from the head, letting each stage process all the
+    /// messages queued to it before it is stopped. Each stage is given at most `timeout` to
+    /// complete; on failure returns the name of the stage together with the error, which tells
+    /// the number of messages the stage still had pending on timeout.
+    pub fn shutdown(mut self, timeout: Duration) -> Result<(), (&'static str, DrainError)> {
+        for stage in &mut self.stages {
+            stage.drain(timeout).map_err(|err| (stage.name(), err))?;
+        }
+        Ok(())
+    }
+}
+
+impl<In> Drop for Pipeline<In> {
+    fn drop(&mut self) {
+        // Terminate the stages in order, so that messages in flight reach the following stages.
+        for stage in self.stages.drain(..) {
+            drop(stage);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ops::ControlFlow;
+    use std::thread;
+
+    use crossbeam_channel::Receiver;
+
+    use super::*;
+    use crate::UProbe;
+
+    #[derive(Default)]
+    struct Double {
+        next: Option<USender<u32>>,
+        delay: Duration,
+    }
+
+    impl UService for Double {
+        type Msg = u32;
+        type Error = String;
+        const NAME: &'static str = "double";
+
+        fn process(&mut self, msg: u32) -> Result<ControlFlow<u8>, Self::Error> {
+            thread::sleep(self.delay);
+            let next = self.next.as_ref().expect("stage is connected");
+            next.send(msg * 2).map_err(|err| err.to_string())?;
+            Ok(ControlFlow::Continue(()))
+        }
+
+        fn terminate(&mut self) {}
+    }
+
+    impl UStage for Double {
+        type Output = u32;
+
+        fn connect(&mut self, next: USender<u32>) { self.next = Some(next) }
+    }
+
+    #[derive(Default)]
+    struct Inc {
+        next: Option<USender<u32>>,
+    }
+
+    impl UService for Inc {
+        type Msg = u32;
+        type Error = String;
+        const NAME: &'static str = "inc";
+
+        fn process(&mut self, msg: u32) -> Result<ControlFlow<u8>, Self::Error> {
+            let next = self.next.as_ref().expect("stage is connected");
+            next.send(msg + 1).map_err(|err| err.to_string())?;
+            Ok(ControlFlow::Continue(()))
+        }
+
+        fn terminate(&mut self) {}
+    }
+
+    impl UStage for Inc {
+        type Output = u32;
+
+        fn connect(&mut self, next: USender<u32>) { self.next = Some(next) }
+    }
+
+    #[derive(Default)]
+    struct Show {
+        next: Option<USender<String>>,
+    }
+
+    impl UService for Show {
+        type Msg = u32;
+        type Error = String;
+        const NAME: &'static str = "show";
+
+        fn process(&mut self, msg: u32) -> Result<ControlFlow<u8>, Self::Error> {
+            let next = self.next.as_ref().expect("stage is connected");
+            next.send(msg.to_string()).map_err(|err| err.to_string())?;
+            Ok(ControlFlow::Continue(()))
+        }
+
+        fn terminate(&mut self) {}
+    }
+
+    impl UStage for Show {
+        type Output = String;
+
+        fn connect(&mut self, next: USender<String>) { self.next = Some(next) }
+    }
+
+    /// Forwards messages to the probe, each one waiting for a permit from the gate, if any.
+    struct Collect<T> {
+        out: USender<T>,
+        gate: Option<Receiver<()>>,
+    }
+
+    impl<T> Collect<T> {
+        fn new(probe: &UProbe<T>) -> Self { Self { out: probe.sender(), gate: None } }
+    }
+
+    impl<T: Send + 'static> UService for Collect<T> {
+        type Msg = T;
+        type Error = String;
+        const NAME: &'static str = "collect";
+
+        fn process(&mut self, msg: T) -> Result<ControlFlow<u8>, Self::Error> {
+            if let Some(gate) = &self.gate {
+                // A closed gate lets all the messages through.
+                let _ = gate.recv();
+            }
+            let _ = self.out.send(msg);
+            Ok(ControlFlow::Continue(()))
+        }
+
+        fn terminate(&mut self) {}
+    }
+
+    #[test]
+    fn stage_order() {
+        let probe = UProbe::new();
+        let pipeline = Pipeline::builder()
+            .then(Double::default())
+            .then(Inc::default())
+            .then(Show::default())
+            .finish(Collect::new(&probe))
+            .unwrap_or_else(|err| panic!("{err}"));
+        assert_eq!(pipeline.stages().collect::<Vec<_>>(), ["double", "inc", "show", "collect"]);
+        for msg in 1..=3 {
+            pipeline.sender().send(msg).unwrap_or_else(|_| panic!());
+        }
+        assert_eq!(pipeline.shutdown(Duration::from_secs(1)), Ok(()));
+        assert_eq!(probe.drain(), ["3", "5", "7"]);
+    }
+
+    #[test]
+    fn back_pressure() {
+        let probe = UProbe::new();
+        let (permit, gate) = crossbeam_channel::unbounded();
+        let sink = Collect { out: probe.sender(), gate: Some(gate) };
+        let pipeline = PipelineBuilder::with_capacity(1)
+            .then(Double::default())
+            .finish(sink)
+            .unwrap_or_else(|err| panic!("{err}"));
+        let sender = pipeline.sender();
+        assert_eq!(sender.capacity(), Some(1));
+        // The sink holds one message waiting for the gate and has one queued, the first stage
+        // holds one blocked on sending and has one queued.
+        let mut accepted = 0;
+        while sender
+            .send_timeout(accepted, Duration::from_millis(100))
+            .is_ok()
+        {
+            accepted += 1;
+        }
+        assert_eq!(accepted, 4);
+        assert!(probe.is_empty());
+
+        permit.send(()).unwrap_or_else(|_| panic!());
+        assert_eq!(sender.send_timeout(accepted, Duration::from_secs(1)), Ok(()));
+        assert_eq!(probe.drain(), [0]);
+
+        drop(permit);
+        assert_eq!(pipeline.shutdown(Duration::from_secs(1)), Ok(()));
+        assert_eq!(probe.drain(), [2, 4, 6, 8]);
+    }
+
+    #[test]
+    fn shutdown_drains_from_head() {
+        let probe = UProbe::new();
+        let first = Double { delay: Duration::from_millis(10), ..Double::default() };
+        let pipeline = Pipeline::builder()
+            .then(first)
+            .then(Inc::default())
+            .finish(Collect::new(&probe))
+            .unwrap_or_else(|err| panic!("{err}"));
+        for msg in 1..=5 {
+            pipeline.sender().send(msg).unwrap_or_else(|_| panic!());
+        }
+        assert_eq!(pipeline.shutdown(Duration::from_secs(1)), Ok(()));
+        assert_eq!(probe.drain(), [3, 5, 7, 9, 11]);
+    }
+
+    #[test]
+    fn shutdown_timeout() {
+        let probe = UProbe::new();
+        let first = Double { delay: Duration::from_millis(200), ..Double::default() };
+        let pipeline = Pipeline::builder()
+            .then(first)
+            .finish(Collect::new(&probe))
+            .unwrap_or_else(|err| panic!("{err}"));
+        for msg in 1..=3 {
+            pipeline.sender().send(msg).unwrap_or_else(|_| panic!());
+        }
+        let (stage, err) = pipeline
+            .shutdown(Duration::from_millis(20))
+            .expect_err("first stage is too slow");
+        assert_eq!(stage, "double");
+        assert!(matches!(err, DrainError::Timeout(pending) if pending >= 2), "{err}");
+        // The stages which were not shut down are terminated on drop, in order.
+        assert_eq!(probe.drain(), [2, 4, 6]);
+    }
+
+    #[test]
+    fn drop_in_order() {
+        let probe = UProbe::new();
+        let first = Double { delay: Duration::from_millis(10), ..Double::default() };
+        let pipeline = Pipeline::builder()
+            .then(first)
+            .then(Inc::default())
+            .finish(Collect::new(&probe))
+            .unwrap_or_else(|err| panic!("{err}"));
+        for msg in 1..=5 {
+            pipeline.sender().send(msg).unwrap_or_else(|_| panic!());
+        }
+        drop(pipeline);
+        assert_eq!(probe.drain(), [3, 5, 7, 9, 11]);
+    }
+}
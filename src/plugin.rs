@@ -0,0 +1,315 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::type_name;
+use std::ffi::{CStr, OsStr, c_char};
+use std::fmt::{self, Display, Formatter};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
+
+use libloading::Library;
+
+use crate::{DynUThread, RegistryError, ServiceRegistry, USender};
+
+/// Version of the plugin declaration layout; checked before anything else is read from a plugin.
+pub const PLUGIN_ABI_VERSION: u32 = 2;
+
+/// Version of this crate which plugins must be built against.
+pub const PLUGIN_CRATE_VERSION: &CStr = c_str(concat!(env!("CARGO_PKG_VERSION"), "\0"));
+
+/// Version of the compiler which plugins must be built with, since service types and the
+/// registrar are passed to plugins with the Rust layout.
+pub const PLUGIN_RUSTC_VERSION: &CStr = c_str(concat!(env!("MICROSERVICES_RUSTC_VERSION"), "\0"));
+
+/// Name of the symbol exported by plugin libraries with [`declare_plugin!`](crate::declare_plugin).
+pub const PLUGIN_SYMBOL: &[u8] = b"MICROSERVICES_PLUGIN\0";
+
+const fn c_str(s: &'static str) -> &'static CStr {
+    match CStr::from_bytes_with_nul(s.as_bytes()) {
+        Ok(s) => s,
+        Err(_) => panic!("string must be NUL-terminated and contain no other NUL bytes"),
+    }
+}
+
+/// Entry point called by the host to register the plugin services.
+pub type PluginEntry = unsafe extern "C" fn(registrar: *mut PluginRegistrar<'_>);
+
+/// Declaration exported by a plugin library, produced by the
+/// [`declare_plugin!`](crate::declare_plugin) macro.
+///
+/// The declaration has the C layout with NUL-terminated strings, so the host can check the
+/// versions before relying on any Rust type of the plugin.
+#[repr(C)]
+pub struct PluginDecl {
+    pub abi_version: u32,
+    pub rustc_version: *const c_char,
+    pub crate_version: *const c_char,
+    pub name: *const c_char,
+    pub register: PluginEntry,
+}
+
+// The pointers refer to immutable static strings.
+unsafe impl Sync for PluginDecl {}
+
+/// Declares the library as a plugin, with the `register` function spawning the plugin services.
+///
+/// The plugin must be built as a `cdylib` with the same compiler and the same version of this
+/// crate as the host; the host rejects plugins built otherwise. The dependencies of this crate
+/// must be of the same versions too, since their types, like the channels inside [`USender`],
+/// cross the library boundary; build the plugin with a copy of the host `Cargo.lock` to ensure
+/// that, as the host can't check it.
+#[macro_export]
+macro_rules! declare_plugin {
+    ($name:literal, $register:path) => {
+        #[unsafe(no_mangle)]
+        pub static MICROSERVICES_PLUGIN: $crate::PluginDecl = $crate::PluginDecl {
+            abi_version: $crate::PLUGIN_ABI_VERSION,
+            rustc_version: $crate::PLUGIN_RUSTC_VERSION.as_ptr(),
+            crate_version: $crate::PLUGIN_CRATE_VERSION.as_ptr(),
+            name: concat!($name, "\0").as_ptr().cast(),
+            register: {
+                unsafe extern "C" fn __microservices_plugin_entry(
+                    registrar: *mut $crate::PluginRegistrar<'_>,
+                ) {
+                    unsafe { $crate::PluginRegistrar::run(registrar, $register) }
+                }
+                __microservices_plugin_entry
+            },
+        };
+    };
+}
+
+/// Handle provided to the plugin `register` function to add the plugin services to the host.
+pub struct PluginRegistrar<'a> {
+    registry: &'a mut ServiceRegistry,
+    services: Vec<String>,
+    /// Result of the plugin `register` function; `None` if it has panicked.
+    outcome: Option<Result<(), RegistryError>>,
+}
+
+impl PluginRegistrar<'_> {
+    /// Runs the plugin `register` function; called from the entry point generated by
+    /// [`declare_plugin!`](crate::declare_plugin).
+    ///
+    /// # Safety
+    ///
+    /// `registrar` must be the pointer passed by the host to the plugin entry point.
+    #[doc(hidden)]
+    pub unsafe fn run(registrar: *mut Self, register: fn(&mut Self) -> Result<(), RegistryError>) {
+        let registrar = unsafe { &mut *registrar };
+        // Unwinding across the entry point would abort the host.
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| register(registrar)));
+        registrar.outcome = outcome.ok();
+    }
+
+    pub fn register(
+        &mut self,
+        name: impl ToString,
+        thread: impl Into<DynUThread>,
+    ) -> Result<(), RegistryError> {
+        let name = name.to_string();
+        self.registry.register(&name, thread)?;
+        self.services.push(name);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum PluginError {
+    Library(libloading::Error),
+    /// The plugin declaration layout version differs from [`PLUGIN_ABI_VERSION`].
+    Abi(u32),
+    /// The plugin is built with a different compiler version.
+    Rustc(String),
+    /// The plugin is built against a different version of this crate.
+    Version(String),
+    /// The plugin has panicked while registering its services.
+    Panicked,
+    Duplicate(String),
+    Registry(RegistryError),
+}
+
+impl Display for PluginError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginError::Library(err) => write!(f, "unable to load plugin library: {err}"),
+            PluginError::Abi(version) => write!(
+                f,
+                "plugin uses ABI version {version}, while version {PLUGIN_ABI_VERSION} is required"
+            ),
+            PluginError::Rustc(version) => write!(
+                f,
+                "plugin is built with {version}, while the host is built with {}",
+                PLUGIN_RUSTC_VERSION.to_string_lossy()
+            ),
+            PluginError::Version(version) => write!(
+                f,
+                "plugin is built with microservices v{version}, while the host uses v{}",
+                PLUGIN_CRATE_VERSION.to_string_lossy()
+            ),
+            PluginError::Panicked => f.write_str("plugin has panicked while registering services"),
+            PluginError::Duplicate(name) => write!(f, "plugin {name} is already loaded"),
+            PluginError::Registry(err) => write!(f, "unable to register plugin service: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PluginError::Library(err) => Some(err),
+            PluginError::Registry(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<libloading::Error> for PluginError {
+    fn from(err: libloading::Error) -> Self { PluginError::Library(err) }
+}
+
+impl From<RegistryError> for PluginError {
+    fn from(err: RegistryError) -> Self { PluginError::Registry(err) }
+}
+
+#[derive(Debug)]
+struct Plugin {
+    name: String,
+    services: Vec<String>,
+    library: Library,
+}
+
+/// Services loaded from plugin libraries.
+///
+/// Dropping the host terminates all the plugin services before unloading the libraries.
+#[derive(Debug, Default)]
+pub struct PluginHost {
+    // Declared before the plugins, so that the services are dropped before their libraries.
+    services: ServiceRegistry,
+    plugins: Vec<Plugin>,
+}
+
+impl PluginHost {
+    pub fn new() -> Self { Self::default() }
+
+    /// Loads the plugin library and starts its services. Returns the plugin name.
+    ///
+    /// # Safety
+    ///
+    /// Loading a library runs its initialization code, and the plugin declaration is trusted to
+    /// match the one of the host once the versions are checked. The plugin must be built with the
+    /// same versions of the dependencies as the host, which is not checked; see
+    /// [`declare_plugin!`](crate::declare_plugin). Senders and other values obtained from the
+    /// plugin services must be dropped before the plugin is unloaded.
+    pub unsafe fn load(&mut self, path: impl AsRef<OsStr>) -> Result<&str, PluginError> {
+        let library = unsafe { Library::new(path) }?;
+        let decl = unsafe { &**library.get::<*const PluginDecl>(PLUGIN_SYMBOL)? };
+        // The declaration layout can be relied on only once the ABI version is checked, and the
+        // Rust types only once the compiler and crate versions match.
+        if decl.abi_version != PLUGIN_ABI_VERSION {
+            return Err(PluginError::Abi(decl.abi_version));
+        }
+        let version = |ptr| unsafe { CStr::from_ptr(ptr) };
+        let rustc_version = version(decl.rustc_version);
+        if rustc_version != PLUGIN_RUSTC_VERSION {
+            return Err(PluginError::Rustc(rustc_version.to_string_lossy().into_owned()));
+        }
+        let crate_version = version(decl.crate_version);
+        if crate_version != PLUGIN_CRATE_VERSION {
+            return Err(PluginError::Version(crate_version.to_string_lossy().into_owned()));
+        }
+        let name = version(decl.name).to_string_lossy().into_owned();
+        if self.plugins.iter().any(|plugin| plugin.name == name) {
+            return Err(PluginError::Duplicate(name));
+        }
+
+        let mut registrar = PluginRegistrar {
+            registry: &mut self.services,
+            services: vec![],
+            outcome: None,
+        };
+        unsafe { (decl.register)(&mut registrar) };
+        let services = registrar.services;
+        let res = match registrar.outcome {
+            Some(res) => res.map_err(PluginError::from),
+            None => Err(PluginError::Panicked),
+        };
+        if let Err(err) = res {
+            for name in services.iter().rev() {
+                self.services.unregister(name);
+            }
+            return Err(err);
+        }
+        #[cfg(feature = "log")]
+        log::info!("loaded plugin {name} with services {}", services.join(", "));
+        self.plugins.push(Plugin { name, services, library });
+        Ok(&self.plugins.last().expect("plugin is just added").name)
+    }
+
+    /// Terminates the plugin services, letting each one process its queued messages within the
+    /// `timeout`, and unloads the plugin library. Returns whether the plugin was loaded.
+    pub fn unload(&mut self, name: &str, timeout: Duration) -> bool {
+        let Some(pos) = self.plugins.iter().position(|plugin| plugin.name == name) else {
+            return false;
+        };
+        let plugin = self.plugins.remove(pos);
+        for service in plugin.services.iter().rev() {
+            if let Some(mut thread) = self.services.unregister(service) {
+                let _ = thread.drain(timeout);
+            }
+        }
+        #[cfg(feature = "log")]
+        log::info!("unloading plugin {name}");
+        drop(plugin.library);
+        true
+    }
+
+    /// Names of the loaded plugins, in the order of loading.
+    pub fn plugins(&self) -> impl Iterator<Item = &str> {
+        self.plugins.iter().map(|plugin| plugin.name.as_str())
+    }
+
+    /// Names of the services started by the plugin.
+    pub fn plugin_services(&self, name: &str) -> Option<impl Iterator<Item = &str>> {
+        let plugin = self.plugins.iter().find(|plugin| plugin.name == name)?;
+        Some(plugin.services.iter().map(String::as_str))
+    }
+
+    pub fn services(&self) -> &ServiceRegistry { &self.services }
+
+    /// Sender to the named plugin service, checking the message type by its name.
+    ///
+    /// # Safety
+    ///
+    /// `Msg` must be the same type as the one used by the plugin service; see
+    /// [`DynUThread::sender_unchecked`].
+    pub unsafe fn sender<Msg: 'static>(&self, name: &str) -> Result<USender<Msg>, RegistryError> {
+        let thread = self
+            .services
+            .get(name)
+            .ok_or_else(|| RegistryError::NotFound(name.to_owned()))?;
+        unsafe { thread.sender_unchecked() }.ok_or_else(|| RegistryError::TypeMismatch {
+            name: name.to_owned(),
+            expected: type_name::<Msg>(),
+            actual: thread.msg_type(),
+        })
+    }
+}
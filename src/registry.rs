@@ -0,0 +1,394 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::{Any, type_name};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::time::Duration;
+
+use crate::{DrainError, USender, UService, UThread};
+
+trait AnyUThread: Send {
+    fn name(&self) -> &'static str;
+    fn msg_type(&self) -> &'static str;
+    fn sender(&self) -> Box<dyn Any + Send>;
+    fn pending(&self) -> usize;
+    fn pause(&self);
+    fn resume(&self);
+    fn drain(&mut self, timeout: Duration) -> Result<(), DrainError>;
+}
+
+impl<S: UService> AnyUThread for UThread<S>
+where S::Msg: 'static
+{
+    fn name(&self) -> &'static str { S::NAME }
+    fn msg_type(&self) -> &'static str { type_name::<S::Msg>() }
+    fn sender(&self) -> Box<dyn Any + Send> { Box::new(UThread::sender(self)) }
+    fn pending(&self) -> usize { UThread::pending(self) }
+    fn pause(&self) { UThread::pause(self) }
+    fn resume(&self) { UThread::resume(self) }
+    fn drain(&mut self, timeout: Duration) -> Result<(), DrainError> {
+        UThread::drain(self, timeout)
+    }
+}
+
+/// Service thread handle with the service type erased.
+///
+/// Dropping the handle terminates the service the same way as dropping [`UThread`] does.
+pub struct DynUThread(Box<dyn AnyUThread>);
+
+impl<S: UService> From<UThread<S>> for DynUThread
+where S::Msg: 'static
+{
+    fn from(thread: UThread<S>) -> Self { Self(Box::new(thread)) }
+}
+
+impl Debug for DynUThread {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DynUThread")
+            .field("name", &self.name())
+            .field("msg_type", &self.msg_type())
+            .finish()
+    }
+}
+
+impl DynUThread {
+    /// Name of the service.
+    pub fn name(&self) -> &'static str { self.0.name() }
+
+    /// Name of the service message type.
+    pub fn msg_type(&self) -> &'static str { self.0.msg_type() }
+
+    /// Sender to the service, if the service message type is `Msg`.
+    pub fn sender<Msg: 'static>(&self) -> Option<USender<Msg>> {
+        self.0.sender().downcast().ok().map(|sender| *sender)
+    }
+
+    /// Sender to the service, if the name of the service message type matches `Msg`.
+    ///
+    /// Unlike [`Self::sender`], does not rely on type identifiers, which differ for the same types
+    /// compiled into separate binaries, such as plugin libraries.
+    ///
+    /// # Safety
+    ///
+    /// `Msg` must be the same type as the service message type, compiled by the same compiler.
+    pub unsafe fn sender_unchecked<Msg: 'static>(&self) -> Option<USender<Msg>> {
+        if self.msg_type() != type_name::<Msg>() {
+            return None;
+        }
+        let sender = Box::into_raw(self.0.sender()) as *mut USender<Msg>;
+        Some(*unsafe { Box::from_raw(sender) })
+    }
+
+    pub fn pending(&self) -> usize { self.0.pending() }
+
+    pub fn pause(&self) { self.0.pause() }
+
+    pub fn resume(&self) { self.0.resume() }
+
+    /// Terminates the service once it processes all queued messages; see [`UThread::drain`].
+    pub fn drain(&mut self, timeout: Duration) -> Result<(), DrainError> { self.0.drain(timeout) }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum RegistryError {
+    Duplicate(String),
+    NotFound(String),
+    TypeMismatch {
+        name: String,
+        expected: &'static str,
+        actual: &'static str,
+    },
+}
+
+impl Display for RegistryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Duplicate(name) => write!(f, "service {name} is already registered"),
+            RegistryError::NotFound(name) => write!(f, "service {name} is not registered"),
+            RegistryError::TypeMismatch { name, expected, actual } => write!(
+                f,
+                "service {name} receives messages of type {actual}, while {expected} was requested"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+/// Collection of running services of different types, addressed by name.
+///
+/// Dropping the registry terminates all the services, in the reverse order of their
+/// registration.
+#[derive(Debug, Default)]
+pub struct ServiceRegistry {
+    services: Vec<(String, DynUThread)>,
+}
+
+impl ServiceRegistry {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn register(
+        &mut self,
+        name: impl ToString,
+        thread: impl Into<DynUThread>,
+    ) -> Result<(), RegistryError> {
+        let name = name.to_string();
+        if self.get(&name).is_some() {
+            return Err(RegistryError::Duplicate(name));
+        }
+        self.services.push((name, thread.into()));
+        Ok(())
+    }
+
+    /// Removes the service from the registry, returning its handle.
+    pub fn unregister(&mut self, name: &str) -> Option<DynUThread> {
+        let pos = self.services.iter().position(|(n, _)| n == name)?;
+        Some(self.services.remove(pos).1)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DynUThread> {
+        self.services
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, thread)| thread)
+    }
+
+    /// Sender to the named service, checking that the service receives messages of type `Msg`.
+    pub fn sender<Msg: 'static>(&self, name: &str) -> Result<USender<Msg>, RegistryError> {
+        let thread = self
+            .get(name)
+            .ok_or_else(|| RegistryError::NotFound(name.to_owned()))?;
+        thread.sender().ok_or_else(|| RegistryError::TypeMismatch {
+            name: name.to_owned(),
+            expected: type_name::<Msg>(),
+            actual: thread.msg_type(),
+        })
+    }
+
+    /// Names of the registered services, in the order of registration.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.services.iter().map(|(n, _)| n.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool { self.services.is_empty() }
+
+    pub fn len(&self) -> usize { self.services.len() }
+
+    /// Terminates all services in the reverse order of their registration, letting each one
+    /// process its queued messages within the `timeout`. Returns the names of the services which
+    /// did not complete in time, which are joined when the registry is dropped, or have
+    /// panicked.
+    pub fn terminate_all(&mut self, timeout: Duration) -> Vec<String> {
+        let mut failed = vec![];
+        for (name, thread) in self.services.iter_mut().rev() {
+            if thread.drain(timeout).is_err() {
+                failed.push(name.clone());
+            }
+        }
+        failed
+    }
+}
+
+impl Drop for ServiceRegistry {
+    fn drop(&mut self) {
+        while let Some((_, thread)) = self.services.pop() {
+            drop(thread);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ops::ControlFlow;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use super::*;
+    use crate::UProbe;
+
+    /// Forwards messages to the probe, recording its termination in the log; panics on
+    /// `u32::MAX`.
+    struct Node {
+        id: &'static str,
+        out: USender<u32>,
+        delay: Duration,
+        log: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Node {
+        fn new(id: &'static str, probe: &UProbe<u32>, log: &Arc<Mutex<Vec<&'static str>>>) -> Self {
+            Self {
+                id,
+                out: probe.sender(),
+                delay: Duration::ZERO,
+                log: log.clone(),
+            }
+        }
+
+        fn spawn(self) -> UThread<Self> { UThread::new(self, None) }
+    }
+
+    impl UService for Node {
+        type Msg = u32;
+        type Error = String;
+        const NAME: &'static str = "node";
+
+        fn process(&mut self, msg: u32) -> Result<ControlFlow<u8>, Self::Error> {
+            thread::sleep(self.delay);
+            assert_ne!(msg, u32::MAX, "test panic");
+            let _ = self.out.send(msg);
+            Ok(ControlFlow::Continue(()))
+        }
+
+        fn terminate(&mut self) { self.log.lock().expect("poisoned").push(self.id) }
+    }
+
+    struct Text;
+
+    impl UService for Text {
+        type Msg = String;
+        type Error = String;
+        const NAME: &'static str = "text";
+
+        fn process(&mut self, _: String) -> Result<ControlFlow<u8>, Self::Error> {
+            Ok(ControlFlow::Continue(()))
+        }
+
+        fn terminate(&mut self) {}
+    }
+
+    #[test]
+    fn typed_sender() {
+        let probe = UProbe::new();
+        let log = Arc::default();
+        let mut registry = ServiceRegistry::new();
+        registry
+            .register("a", Node::new("a", &probe, &log).spawn())
+            .unwrap_or_else(|err| panic!("{err}"));
+        registry
+            .register("text", UThread::new(Text, None))
+            .unwrap_or_else(|err| panic!("{err}"));
+
+        let sender = registry
+            .sender::<u32>("a")
+            .unwrap_or_else(|err| panic!("{err}"));
+        sender.send(1).unwrap_or_else(|_| panic!());
+        assert_eq!(
+            registry.sender::<u32>("text").map(|_| ()),
+            Err(RegistryError::TypeMismatch {
+                name: "text".to_owned(),
+                expected: type_name::<u32>(),
+                actual: type_name::<String>(),
+            })
+        );
+        assert_eq!(
+            registry.sender::<u32>("b").map(|_| ()),
+            Err(RegistryError::NotFound("b".to_owned()))
+        );
+        assert_eq!(registry.terminate_all(Duration::from_secs(1)), Vec::<String>::new());
+        assert_eq!(probe.drain(), [1]);
+    }
+
+    #[test]
+    fn duplicate() {
+        let probe = UProbe::new();
+        let log = Arc::default();
+        let mut registry = ServiceRegistry::new();
+        registry
+            .register("a", Node::new("first", &probe, &log).spawn())
+            .unwrap_or_else(|err| panic!("{err}"));
+        assert_eq!(
+            registry.register("a", Node::new("second", &probe, &log).spawn()),
+            Err(RegistryError::Duplicate("a".to_owned()))
+        );
+        assert_eq!(registry.len(), 1);
+        // The rejected service is dropped and terminated right away.
+        assert_eq!(*log.lock().expect("poisoned"), ["second"]);
+        drop(registry);
+        assert_eq!(*log.lock().expect("poisoned"), ["second", "first"]);
+    }
+
+    #[test]
+    fn terminate_in_reverse_order() {
+        let probe = UProbe::new();
+        let log = Arc::default();
+        let mut registry = ServiceRegistry::new();
+        for id in ["a", "b", "c"] {
+            registry
+                .register(id, Node::new(id, &probe, &log).spawn())
+                .unwrap_or_else(|err| panic!("{err}"));
+        }
+        assert_eq!(registry.names().collect::<Vec<_>>(), ["a", "b", "c"]);
+        assert_eq!(registry.terminate_all(Duration::from_secs(1)), Vec::<String>::new());
+        assert_eq!(*log.lock().expect("poisoned"), ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn terminate_failures() {
+        let probe = UProbe::new();
+        let log = Arc::default();
+        let mut registry = ServiceRegistry::new();
+        let slow = Node {
+            delay: Duration::from_millis(200),
+            ..Node::new("slow", &probe, &log)
+        };
+        for (id, node) in [
+            ("slow", slow),
+            ("fast", Node::new("fast", &probe, &log)),
+            ("broken", Node::new("broken", &probe, &log)),
+        ] {
+            registry
+                .register(id, node.spawn())
+                .unwrap_or_else(|err| panic!("{err}"));
+        }
+        let sender = |name| {
+            registry
+                .sender::<u32>(name)
+                .unwrap_or_else(|err| panic!("{err}"))
+        };
+        sender("slow").send(1).unwrap_or_else(|_| panic!());
+        // Waits for the panic to complete, so that the service is not reported as timed out.
+        let broken = sender("broken");
+        broken.send(u32::MAX).unwrap_or_else(|_| panic!());
+        while broken.send(0).is_ok() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(registry.terminate_all(Duration::from_millis(20)), ["broken", "slow"]);
+        assert_eq!(*log.lock().expect("poisoned"), ["fast"]);
+        // The slow service completes when the registry is dropped.
+        drop(registry);
+        assert_eq!(*log.lock().expect("poisoned"), ["fast", "slow"]);
+        assert_eq!(probe.drain(), [1]);
+    }
+
+    #[test]
+    fn unchecked_sender() {
+        let probe = UProbe::new();
+        let log = Arc::default();
+        let thread = DynUThread::from(Node::new("a", &probe, &log).spawn());
+        assert!(unsafe { thread.sender_unchecked::<String>() }.is_none());
+        assert!(unsafe { thread.sender_unchecked::<i32>() }.is_none());
+        let sender = unsafe { thread.sender_unchecked::<u32>() }.expect("message type matches");
+        sender.send(1).unwrap_or_else(|_| panic!());
+        drop(thread);
+        assert_eq!(probe.drain(), [1]);
+    }
+}
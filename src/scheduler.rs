@@ -0,0 +1,995 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "store")]
+use std::io::{self, Read};
+use std::ops::ControlFlow;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "store")]
+use crate::store::{read_chunk, write_chunk};
+use crate::{Clock, SystemClock, UError, UResult, USender, UService};
+#[cfg(feature = "store")]
+use crate::{KvStore, LogEvent};
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum CronError {
+    /// Expression doesn't have exactly five fields.
+    FieldCount(usize),
+    /// Field value is not a number or is out of the allowed range.
+    Value(String),
+}
+
+impl Display for CronError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CronError::FieldCount(count) => {
+                write!(f, "cron expression must have 5 fields, while {count} are given")
+            }
+            CronError::Value(field) => write!(f, "invalid cron field '{field}'"),
+        }
+    }
+}
+
+impl std::error::Error for CronError {}
+
+/// Cron expression in the standard five-field format (`minute hour day-of-month month
+/// day-of-week`), evaluated in UTC.
+///
+/// Each field is `*`, a number, a range `a-b`, or a comma-separated list of those, optionally
+/// followed by a step `/n`. Days of week are numbered from 0 (Sunday) to 6; 7 is also accepted for
+/// Sunday. As in the classic cron, if both day of month and day of week are restricted, a day
+/// matching either of them matches; a field starting with `*`, like `*/2`, is not restricted.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Cron {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    /// Returns the first time matching the expression strictly after `after`, if any within the
+    /// following eight years.
+    pub fn next_after(&self, after: SystemTime) -> Option<SystemTime> {
+        let secs = after.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        let mut minute = secs / 60 + 1;
+        let limit = minute + 8 * 366 * 24 * 60;
+        while minute < limit {
+            let days = (minute / (24 * 60)) as i64;
+            let (_, month, day) = civil_from_days(days);
+            if !bit(self.months, month) {
+                let (year, month, _) = civil_from_days(days);
+                let (year, month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+                minute = days_from_civil(year, month, 1) as u64 * 24 * 60;
+                continue;
+            }
+            let weekday = (days + 4).rem_euclid(7) as u32;
+            let day_matches = if self.any_day || self.any_weekday {
+                bit(self.days, day) && bit(self.weekdays, weekday)
+            } else {
+                bit(self.days, day) || bit(self.weekdays, weekday)
+            };
+            if !day_matches {
+                minute = (days as u64 + 1) * 24 * 60;
+                continue;
+            }
+            let hour = (minute / 60 % 24) as u32;
+            if !bit(self.hours, hour) {
+                minute = (minute / 60 + 1) * 60;
+                continue;
+            }
+            if !bit(self.minutes, (minute % 60) as u32) {
+                minute += 1;
+                continue;
+            }
+            return Some(UNIX_EPOCH + Duration::from_secs(minute * 60));
+        }
+        None
+    }
+}
+
+impl FromStr for Cron {
+    type Err = CronError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(CronError::FieldCount(fields.len()));
+        };
+        let mut weekdays_mask = parse_field(weekdays, 0, 7)?;
+        if bit(weekdays_mask, 7) {
+            weekdays_mask = (weekdays_mask | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            source: fields.join(" "),
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days: parse_field(days, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            weekdays: weekdays_mask,
+            any_day: days.starts_with('*'),
+            any_weekday: weekdays.starts_with('*'),
+        })
+    }
+}
+
+impl Display for Cron {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str(&self.source) }
+}
+
+fn bit(mask: u64, no: u32) -> bool { mask & (1 << no) != 0 }
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, CronError> {
+    let err = || CronError::Value(field.to_owned());
+    let num = |s: &str| {
+        s.parse::<u32>()
+            .ok()
+            .filter(|val| (min..=max).contains(val))
+            .ok_or_else(err)
+    };
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(err)?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((from, to)) => (num(from)?, num(to)?),
+                None if step > 1 => (num(range)?, max),
+                None => {
+                    let val = num(range)?;
+                    (val, val)
+                }
+            },
+        };
+        if from > to {
+            return Err(err());
+        }
+        for val in (from..=to).step_by(step as usize) {
+            mask |= 1 << val;
+        }
+    }
+    Ok(mask)
+}
+
+// Conversion between days since the Unix epoch and proleptic Gregorian calendar dates, after
+// Howard Hinnant's `chrono`-compatible date algorithms.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Schedule {
+    /// Run once at the given time.
+    Once(SystemTime),
+    /// Run repeatedly with the given non-zero interval, starting one interval after the job is
+    /// scheduled.
+    Every(Duration),
+    /// Run at times matching the cron expression.
+    Cron(Cron),
+}
+
+impl Schedule {
+    fn first(&self, now: SystemTime) -> Option<SystemTime> {
+        match self {
+            Schedule::Once(at) => Some(*at),
+            Schedule::Every(interval) => now.checked_add(*interval),
+            Schedule::Cron(cron) => cron.next_after(now),
+        }
+    }
+
+    fn next(&self, prev: SystemTime, now: SystemTime) -> Option<SystemTime> {
+        match self {
+            Schedule::Once(_) => None,
+            Schedule::Every(interval) => {
+                // Skip runs missed while the scheduler was stopped or busy.
+                let missed = now
+                    .duration_since(prev)
+                    .unwrap_or_default()
+                    .as_nanos()
+                    .checked_div(interval.as_nanos())?;
+                let runs = u32::try_from(missed + 1).unwrap_or(u32::MAX);
+                interval
+                    .checked_mul(runs)
+                    .and_then(|delay| prev.checked_add(delay))
+            }
+            Schedule::Cron(cron) => cron.next_after(now),
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Job<M> {
+    pub id: String,
+    /// Name of the target registered with the scheduler, which receives the payload.
+    pub target: String,
+    pub schedule: Schedule,
+    pub payload: M,
+    /// Time of the next run.
+    pub next: SystemTime,
+}
+
+/// Persistent storage for the scheduled jobs.
+pub trait JobStore<M>: Send {
+    fn load(&self) -> UResult<Vec<Job<M>>>;
+    fn save(&mut self, job: &Job<M>) -> UResult;
+    fn remove(&mut self, id: &str) -> UResult;
+}
+
+/// Job storage persisting jobs in a [`KvStore`], with payloads encoded as [`LogEvent`]s.
+#[cfg(feature = "store")]
+#[derive(Debug)]
+pub struct KvJobStore<K: KvStore> {
+    store: K,
+    prefix: Vec<u8>,
+}
+
+#[cfg(feature = "store")]
+impl<K: KvStore> KvJobStore<K> {
+    pub fn new(store: K, name: &str) -> Self {
+        Self { store, prefix: format!("jobs/{name}/").into_bytes() }
+    }
+
+    pub fn into_store(self) -> K { self.store }
+
+    fn key(&self, id: &str) -> Vec<u8> {
+        let mut key = self.prefix.clone();
+        key.extend(id.as_bytes());
+        key
+    }
+
+    fn encode<M: LogEvent>(job: &Job<M>) -> io::Result<Vec<u8>> {
+        let mut data = vec![];
+        write_chunk(&mut data, job.id.as_bytes())?;
+        write_chunk(&mut data, job.target.as_bytes())?;
+        match &job.schedule {
+            Schedule::Once(at) => {
+                data.push(0);
+                write_time(&mut data, *at)?;
+            }
+            Schedule::Every(interval) => {
+                data.push(1);
+                write_duration(&mut data, *interval);
+            }
+            Schedule::Cron(cron) => {
+                data.push(2);
+                write_chunk(&mut data, cron.source.as_bytes())?;
+            }
+        }
+        write_time(&mut data, job.next)?;
+        write_chunk(&mut data, &job.payload.to_bytes())?;
+        Ok(data)
+    }
+
+    fn decode<M: LogEvent>(mut data: &[u8]) -> Result<Job<M>, String> {
+        let reader = &mut data;
+        let string = |reader: &mut &[u8]| -> Result<String, String> {
+            let chunk = read_chunk(reader, false).map_err(|err| err.to_string())?;
+            String::from_utf8(chunk.unwrap_or_default()).map_err(|err| err.to_string())
+        };
+        let id = string(reader)?;
+        let target = string(reader)?;
+        let mut tag = [0u8; 1];
+        reader.read_exact(&mut tag).map_err(|err| err.to_string())?;
+        let schedule = match tag[0] {
+            0 => Schedule::Once(read_time(reader).map_err(|err| err.to_string())?),
+            1 => match read_duration(reader).map_err(|err| err.to_string())? {
+                interval if interval.is_zero() => return Err("zero job interval".to_owned()),
+                interval => Schedule::Every(interval),
+            },
+            2 => Schedule::Cron(
+                string(reader)?
+                    .parse()
+                    .map_err(|err: CronError| err.to_string())?,
+            ),
+            tag => return Err(format!("unknown schedule type {tag}")),
+        };
+        let next = read_time(reader).map_err(|err| err.to_string())?;
+        let payload = read_chunk(reader, false).map_err(|err| err.to_string())?;
+        let payload = M::from_bytes(&payload.unwrap_or_default()).map_err(|err| err.to_string())?;
+        if !reader.is_empty() {
+            return Err(format!("{} bytes of trailing data after the job", reader.len()));
+        }
+        Ok(Job { id, target, schedule, payload, next })
+    }
+}
+
+#[cfg(feature = "store")]
+fn write_time(data: &mut Vec<u8>, time: SystemTime) -> io::Result<()> {
+    let since_epoch = time
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "time before Unix epoch"))?;
+    write_duration(data, since_epoch);
+    Ok(())
+}
+
+#[cfg(feature = "store")]
+fn write_duration(data: &mut Vec<u8>, duration: Duration) {
+    data.extend(duration.as_secs().to_le_bytes());
+    data.extend(duration.subsec_nanos().to_le_bytes());
+}
+
+#[cfg(feature = "store")]
+fn read_time(reader: &mut impl Read) -> io::Result<SystemTime> {
+    UNIX_EPOCH
+        .checked_add(read_duration(reader)?)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "time is out of range"))
+}
+
+#[cfg(feature = "store")]
+fn read_duration(reader: &mut impl Read) -> io::Result<Duration> {
+    let mut secs = [0u8; 8];
+    let mut nanos = [0u8; 4];
+    reader.read_exact(&mut secs)?;
+    reader.read_exact(&mut nanos)?;
+    Duration::from_secs(u64::from_le_bytes(secs))
+        .checked_add(Duration::from_nanos(u32::from_le_bytes(nanos) as u64))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "duration is out of range"))
+}
+
+#[cfg(feature = "store")]
+impl<M: LogEvent, K: KvStore> JobStore<M> for KvJobStore<K> {
+    fn load(&self) -> UResult<Vec<Job<M>>> {
+        self.store
+            .scan(&self.prefix)
+            .map_err(|err| Box::new(err) as UError)?
+            .into_iter()
+            .map(|(_, data)| Self::decode(&data).map_err(|err| Box::new(err) as UError))
+            .collect()
+    }
+
+    fn save(&mut self, job: &Job<M>) -> UResult {
+        let data = Self::encode(job).map_err(|err| Box::new(err) as UError)?;
+        self.store
+            .put(&self.key(&job.id), &data)
+            .map_err(|err| Box::new(err) as UError)
+    }
+
+    fn remove(&mut self, id: &str) -> UResult {
+        self.store
+            .delete(&self.key(id))
+            .map_err(|err| Box::new(err) as UError)
+    }
+}
+
+pub enum SchedulerMsg<M> {
+    /// Registers a recipient of job payloads under the given name.
+    Register(String, USender<M>),
+    Unregister(String),
+    /// Schedules a job, replacing the existing one with the same id.
+    Schedule {
+        id: String,
+        target: String,
+        schedule: Schedule,
+        payload: M,
+    },
+    Cancel(String),
+}
+
+/// Service delivering messages to registered recipients at scheduled times.
+///
+/// Jobs are checked on each service tick, so the scheduler must run with ticks enabled; the tick
+/// interval defines the scheduling precision. Jobs whose target is not registered at the time
+/// they are due are reported as errors; one-time jobs are then retried on the following ticks.
+pub struct Scheduler<M> {
+    clock: Box<dyn Clock>,
+    started: (SystemTime, Instant),
+    targets: HashMap<String, USender<M>>,
+    jobs: HashMap<String, Job<M>>,
+    store: Option<Box<dyn JobStore<M>>>,
+}
+
+impl<M> Default for Scheduler<M> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<M> Scheduler<M> {
+    pub fn new() -> Self { Self::with_clock(SystemClock) }
+
+    /// Creates scheduler measuring time with the `clock`. Wall-clock time of the jobs is computed
+    /// from the system time at the moment of creation and the time elapsed on the `clock`
+    /// since then.
+    pub fn with_clock(clock: impl Clock) -> Self {
+        let started = (SystemTime::now(), clock.now());
+        Self {
+            clock: Box::new(clock),
+            started,
+            targets: HashMap::new(),
+            jobs: HashMap::new(),
+            store: None,
+        }
+    }
+
+    /// Uses the `store` to persist jobs, restoring the jobs saved there before.
+    pub fn with_store(mut self, store: impl JobStore<M> + 'static) -> UResult<Self> {
+        for job in store.load()? {
+            self.jobs.insert(job.id.clone(), job);
+        }
+        self.store = Some(Box::new(store));
+        Ok(self)
+    }
+
+    pub fn now(&self) -> SystemTime { self.started.0 + (self.clock.now() - self.started.1) }
+
+    pub fn job(&self, id: &str) -> Option<&Job<M>> { self.jobs.get(id) }
+
+    pub fn jobs(&self) -> impl Iterator<Item = &Job<M>> { self.jobs.values() }
+
+    pub fn register(&mut self, target: impl ToString, sender: USender<M>) {
+        self.targets.insert(target.to_string(), sender);
+    }
+
+    pub fn unregister(&mut self, target: &str) { self.targets.remove(target); }
+
+    pub fn schedule(
+        &mut self,
+        id: impl ToString,
+        target: impl ToString,
+        schedule: Schedule,
+        payload: M,
+    ) -> UResult {
+        let id = id.to_string();
+        if schedule == Schedule::Every(Duration::ZERO) {
+            return Err(Box::new(format!("job {id} has zero interval")));
+        }
+        let Some(next) = schedule.first(self.now()) else {
+            return Err(Box::new(format!("schedule of job {id} never triggers")));
+        };
+        let job = Job {
+            id: id.clone(),
+            target: target.to_string(),
+            schedule,
+            payload,
+            next,
+        };
+        if let Some(store) = &mut self.store {
+            store.save(&job)?;
+        }
+        self.jobs.insert(id, job);
+        Ok(())
+    }
+
+    pub fn cancel(&mut self, id: &str) -> UResult {
+        if let Some(store) = &mut self.store {
+            store.remove(id)?;
+        }
+        self.jobs.remove(id);
+        Ok(())
+    }
+}
+
+impl<M: Clone> Scheduler<M> {
+    /// Delivers payloads of all due jobs and reschedules them. Returns errors for the jobs which
+    /// could not be processed.
+    pub fn run_due(&mut self) -> Vec<UError> {
+        let now = self.now();
+        let mut errors = Vec::<UError>::new();
+        let mut due = self
+            .jobs
+            .values()
+            .filter(|job| job.next <= now)
+            .collect::<Vec<_>>();
+        due.sort_by_key(|job| job.next);
+        let due = due
+            .into_iter()
+            .map(|job| job.id.clone())
+            .collect::<Vec<_>>();
+        for id in due {
+            let job = self.jobs.get_mut(&id).expect("job is present");
+            let delivered = match self.targets.get(&job.target) {
+                None => {
+                    errors.push(Box::new(format!(
+                        "target {} of job {id} is not registered",
+                        job.target
+                    )));
+                    false
+                }
+                Some(sender) if sender.send(job.payload.clone()).is_err() => {
+                    errors.push(Box::new(format!(
+                        "target {} of job {id} has disconnected",
+                        job.target
+                    )));
+                    self.targets.remove(&job.target);
+                    false
+                }
+                Some(_) => true,
+            };
+            let next = match job.schedule.next(job.next, now) {
+                None if !delivered => continue,
+                None => None,
+                Some(next) => Some(next),
+            };
+            let res = match (next, &mut self.store) {
+                (Some(next), store) => {
+                    job.next = next;
+                    store
+                        .as_mut()
+                        .map(|store| store.save(job))
+                        .unwrap_or(Ok(()))
+                }
+                (None, Some(store)) => store.remove(&id),
+                (None, None) => Ok(()),
+            };
+            if next.is_none() {
+                self.jobs.remove(&id);
+            }
+            if let Err(err) = res {
+                errors.push(err);
+            }
+        }
+        errors
+    }
+}
+
+impl<M: Clone + Send + 'static> UService for Scheduler<M> {
+    type Msg = SchedulerMsg<M>;
+    type Error = String;
+    const NAME: &'static str = "scheduler";
+
+    fn tick(&mut self) -> Result<(), Self::Error> {
+        for err in self.run_due() {
+            self.error_brief(err);
+        }
+        Ok(())
+    }
+
+    fn process(&mut self, msg: Self::Msg) -> Result<ControlFlow<u8>, Self::Error> {
+        match msg {
+            SchedulerMsg::Register(target, sender) => self.register(target, sender),
+            SchedulerMsg::Unregister(target) => self.unregister(&target),
+            SchedulerMsg::Schedule { id, target, schedule, payload } => self
+                .schedule(id, target, schedule, payload)
+                .map_err(|err| err.to_string())?,
+            SchedulerMsg::Cancel(id) => self.cancel(&id).map_err(|err| err.to_string())?,
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn terminate(&mut self) {}
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{MockClock, UProbe};
+
+    fn time(year: i64, month: u32, day: u32, hour: u64, minute: u64) -> SystemTime {
+        let days = days_from_civil(year, month, day) as u64;
+        UNIX_EPOCH + Duration::from_secs(((days * 24 + hour) * 60 + minute) * 60)
+    }
+
+    fn cron(s: &str) -> Cron { s.parse().unwrap_or_else(|err| panic!("{s}: {err}")) }
+
+    #[test]
+    fn civil_days() {
+        for (days, date) in [
+            (0, (1970, 1, 1)),
+            (-1, (1969, 12, 31)),
+            (11016, (2000, 2, 29)),
+            (19782, (2024, 2, 29)),
+            (-135080, (1600, 3, 1)),
+            (157113, (2400, 2, 29)),
+            (2932896, (9999, 12, 31)),
+        ] {
+            assert_eq!(civil_from_days(days), date, "{days}");
+            assert_eq!(days_from_civil(date.0, date.1, date.2), days, "{date:?}");
+        }
+        for days in (-800_000..800_000).step_by(97) {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn fields() {
+        let mask = |vals: &[u32]| vals.iter().fold(0u64, |mask, val| mask | 1 << val);
+        for (field, min, max, expected) in [
+            ("*", 0, 5, mask(&[0, 1, 2, 3, 4, 5])),
+            ("*/15", 0, 59, mask(&[0, 15, 30, 45])),
+            ("*/2", 1, 7, mask(&[1, 3, 5, 7])),
+            ("5/20", 0, 59, mask(&[5, 25, 45])),
+            ("10-20/5", 0, 59, mask(&[10, 15, 20])),
+            ("3", 0, 59, mask(&[3])),
+            ("1,3,5-7", 0, 59, mask(&[1, 3, 5, 6, 7])),
+            ("0-0", 0, 59, mask(&[0])),
+            ("59", 0, 59, mask(&[59])),
+        ] {
+            assert_eq!(parse_field(field, min, max), Ok(expected), "{field}");
+        }
+        for (field, min, max) in [
+            ("", 0, 59),
+            ("60", 0, 59),
+            ("0", 1, 31),
+            ("5-1", 0, 59),
+            ("*/0", 0, 59),
+            ("*/x", 0, 59),
+            ("a", 0, 59),
+            ("1-", 0, 59),
+            ("-1", 0, 59),
+            ("1,,2", 0, 59),
+            ("1-2-3", 0, 59),
+        ] {
+            assert_eq!(
+                parse_field(field, min, max),
+                Err(CronError::Value(field.to_owned())),
+                "{field}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(cron("0 0 * * 7").weekdays, cron("0 0 * * 0").weekdays);
+        assert_eq!(cron("0 0 * * 5-7").weekdays, cron("0 0 * * 0,5,6").weekdays);
+        assert_eq!(cron(" 0  0 * *\t1 ").to_string(), "0 0 * * 1");
+        for (s, err) in [
+            ("0 0 * *", CronError::FieldCount(4)),
+            ("0 0 * * * *", CronError::FieldCount(6)),
+            ("", CronError::FieldCount(0)),
+            ("0 24 * * *", CronError::Value("24".to_owned())),
+            ("0 0 32 * *", CronError::Value("32".to_owned())),
+            ("0 0 * 13 *", CronError::Value("13".to_owned())),
+            ("0 0 * * 8", CronError::Value("8".to_owned())),
+        ] {
+            assert_eq!(s.parse::<Cron>(), Err(err), "{s}");
+        }
+    }
+
+    #[test]
+    fn next_after() {
+        for (expr, after, expected) in [
+            ("*/15 * * * *", time(2024, 1, 1, 0, 7), time(2024, 1, 1, 0, 15)),
+            // Strictly after the given time.
+            ("*/15 * * * *", time(2024, 1, 1, 0, 15), time(2024, 1, 1, 0, 30)),
+            ("0 0 29 2 *", time(2023, 3, 1, 0, 0), time(2024, 2, 29, 0, 0)),
+            ("0 0 29 2 *", time(2024, 2, 29, 0, 0), time(2028, 2, 29, 0, 0)),
+            // Months without the 31st are skipped.
+            ("59 23 31 * *", time(2024, 4, 1, 0, 0), time(2024, 5, 31, 23, 59)),
+            ("0 0 * * *", time(2024, 12, 31, 23, 59), time(2025, 1, 1, 0, 0)),
+            ("30 12 31 12 *", time(2024, 12, 31, 12, 30), time(2025, 12, 31, 12, 30)),
+            ("0 9 * 1 *", time(2024, 2, 1, 0, 0), time(2025, 1, 1, 9, 0)),
+            // Restricted day of month and day of week match either of them.
+            ("0 0 10 * 5", time(2024, 9, 7, 0, 0), time(2024, 9, 10, 0, 0)),
+            ("0 0 10 * 5", time(2024, 9, 10, 0, 0), time(2024, 9, 13, 0, 0)),
+            // A stepped wildcard does not restrict the day, so both of them must match.
+            ("0 0 */2 * 1", time(2024, 9, 1, 0, 0), time(2024, 9, 9, 0, 0)),
+            ("0 0 * * */6", time(2024, 9, 1, 0, 0), time(2024, 9, 7, 0, 0)),
+            ("0 0 * * 7", time(2024, 9, 2, 0, 0), time(2024, 9, 8, 0, 0)),
+            ("0 0 * * 1-5", time(2024, 9, 6, 12, 0), time(2024, 9, 9, 0, 0)),
+        ] {
+            assert_eq!(cron(expr).next_after(after), Some(expected), "{expr}");
+        }
+        assert_eq!(cron("0 0 30 2 *").next_after(time(2024, 1, 1, 0, 0)), None);
+        assert_eq!(cron("* * * * *").next_after(UNIX_EPOCH - Duration::from_secs(1)), None);
+    }
+
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Recorder {
+        fn take(&self) -> Vec<String> { std::mem::take(&mut self.0.lock().unwrap()) }
+    }
+
+    impl JobStore<u32> for Recorder {
+        fn load(&self) -> UResult<Vec<Job<u32>>> { Ok(vec![]) }
+
+        fn save(&mut self, job: &Job<u32>) -> UResult {
+            self.0.lock().unwrap().push(format!("save {}", job.id));
+            Ok(())
+        }
+
+        fn remove(&mut self, id: &str) -> UResult {
+            self.0.lock().unwrap().push(format!("remove {id}"));
+            Ok(())
+        }
+    }
+
+    fn scheduler() -> (Scheduler<u32>, MockClock) {
+        let clock = MockClock::new();
+        (Scheduler::with_clock(clock.clone()), clock)
+    }
+
+    fn schedule(scheduler: &mut Scheduler<u32>, id: &str, schedule: Schedule, payload: u32) {
+        scheduler
+            .schedule(id, "target", schedule, payload)
+            .unwrap_or_else(|err| panic!("{err}"));
+    }
+
+    #[test]
+    fn once_retried_until_registered() {
+        let (mut scheduler, clock) = scheduler();
+        let at = scheduler.now() + Duration::from_secs(10);
+        schedule(&mut scheduler, "once", Schedule::Once(at), 1);
+        clock.advance(Duration::from_secs(9));
+        assert!(scheduler.run_due().is_empty());
+
+        clock.advance(Duration::from_secs(1));
+        let errors = scheduler.run_due();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "target target of job once is not registered");
+        assert_eq!(scheduler.job("once").map(|job| job.next), Some(at));
+
+        let probe = UProbe::new();
+        scheduler.register("target", probe.sender());
+        clock.advance(Duration::from_secs(1));
+        assert!(scheduler.run_due().is_empty());
+        assert_eq!(probe.drain(), vec![1]);
+        assert!(scheduler.job("once").is_none());
+    }
+
+    #[test]
+    fn disconnected_target_removed() {
+        let (mut scheduler, clock) = scheduler();
+        let probe = UProbe::new();
+        scheduler.register("target", probe.sender());
+        drop(probe);
+        schedule(&mut scheduler, "every", Schedule::Every(Duration::from_secs(1)), 1);
+
+        clock.advance(Duration::from_secs(1));
+        let errors = scheduler.run_due();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "target target of job every has disconnected");
+        clock.advance(Duration::from_secs(1));
+        let errors = scheduler.run_due();
+        assert_eq!(errors[0].to_string(), "target target of job every is not registered");
+    }
+
+    #[test]
+    fn missed_runs_skipped() {
+        let (mut scheduler, clock) = scheduler();
+        let probe = UProbe::new();
+        scheduler.register("target", probe.sender());
+        let start = scheduler.now();
+        schedule(&mut scheduler, "every", Schedule::Every(Duration::from_secs(10)), 1);
+
+        clock.advance(Duration::from_secs(35));
+        assert!(scheduler.run_due().is_empty());
+        assert_eq!(probe.drain(), vec![1]);
+        let next = scheduler.job("every").map(|job| job.next);
+        assert_eq!(next, Some(start + Duration::from_secs(40)));
+
+        clock.advance(Duration::from_secs(4));
+        assert!(scheduler.run_due().is_empty());
+        assert!(probe.is_empty());
+        clock.advance(Duration::from_secs(1));
+        assert!(scheduler.run_due().is_empty());
+        assert_eq!(probe.drain(), vec![1]);
+    }
+
+    #[test]
+    fn store_updated_on_run() {
+        let (scheduler, clock) = scheduler();
+        let recorder = Recorder::default();
+        let mut scheduler = scheduler
+            .with_store(recorder.clone())
+            .unwrap_or_else(|err| panic!("{err}"));
+        let probe = UProbe::new();
+        scheduler.register("target", probe.sender());
+        let at = scheduler.now() + Duration::from_secs(1);
+        schedule(&mut scheduler, "once", Schedule::Once(at), 1);
+        schedule(&mut scheduler, "every", Schedule::Every(Duration::from_secs(1)), 2);
+        assert_eq!(recorder.take(), vec!["save once", "save every"]);
+
+        clock.advance(Duration::from_secs(1));
+        assert!(scheduler.run_due().is_empty());
+        let mut ops = recorder.take();
+        ops.sort();
+        assert_eq!(ops, vec!["remove once", "save every"]);
+        let mut payloads = probe.drain();
+        payloads.sort();
+        assert_eq!(payloads, vec![1, 2]);
+
+        scheduler
+            .cancel("every")
+            .unwrap_or_else(|err| panic!("{err}"));
+        assert_eq!(recorder.take(), vec!["remove every"]);
+        assert_eq!(scheduler.jobs().count(), 0);
+    }
+
+    #[test]
+    fn invalid_interval() {
+        let (mut scheduler, _) = scheduler();
+        for interval in [Duration::ZERO, Duration::MAX] {
+            let msg = SchedulerMsg::Schedule {
+                id: "every".to_owned(),
+                target: "target".to_owned(),
+                schedule: Schedule::Every(interval),
+                payload: 1,
+            };
+            assert!(scheduler.process(msg).is_err(), "{interval:?}");
+        }
+        assert_eq!(scheduler.jobs().count(), 0);
+    }
+
+    #[cfg(feature = "store")]
+    mod store {
+        use super::*;
+        use crate::MemStore;
+
+        #[derive(Clone, Eq, PartialEq, Debug)]
+        struct Payload(Vec<u8>);
+
+        impl LogEvent for Payload {
+            type Error = String;
+
+            fn to_bytes(&self) -> Vec<u8> { self.0.clone() }
+
+            fn from_bytes(data: &[u8]) -> Result<Self, Self::Error> {
+                if data.first() == Some(&0xFF) {
+                    return Err("invalid payload".to_owned());
+                }
+                Ok(Self(data.to_vec()))
+            }
+        }
+
+        fn job(id: &str, schedule: Schedule, payload: &[u8]) -> Job<Payload> {
+            Job {
+                id: id.to_owned(),
+                target: "target".to_owned(),
+                schedule,
+                payload: Payload(payload.to_vec()),
+                next: time(2024, 9, 1, 12, 0) + Duration::from_nanos(123_456_789),
+            }
+        }
+
+        #[test]
+        fn round_trip() {
+            let jobs = vec![
+                job("a", Schedule::Once(time(2030, 1, 1, 0, 0) + Duration::from_nanos(1)), b"once"),
+                job("b", Schedule::Every(Duration::new(90, 500)), b""),
+                job("c", Schedule::Every(Duration::MAX), &[0; 300]),
+                job("d", Schedule::Cron(cron("*/5 0-6 1,15 * 1-5")), b"cron"),
+            ];
+            let mut store = KvJobStore::new(MemStore::new(), "test");
+            for job in &jobs {
+                JobStore::save(&mut store, job).unwrap_or_else(|err| panic!("{err}"));
+            }
+            let loaded: Vec<Job<Payload>> = store.load().unwrap_or_else(|err| panic!("{err}"));
+            assert_eq!(loaded, jobs);
+
+            JobStore::<Payload>::remove(&mut store, "b").unwrap_or_else(|err| panic!("{err}"));
+            let loaded: Vec<Job<Payload>> = store.load().unwrap_or_else(|err| panic!("{err}"));
+            assert_eq!(loaded.len(), 3);
+            assert!(loaded.iter().all(|job| job.id != "b"));
+
+            // Jobs of other schedulers are not loaded.
+            let other = KvJobStore::new(store.into_store(), "other");
+            assert!(JobStore::<Payload>::load(&other).is_ok_and(|jobs| jobs.is_empty()));
+        }
+
+        #[test]
+        fn decode_errors() {
+            let data = KvJobStore::<MemStore>::encode(&job(
+                "a",
+                Schedule::Every(Duration::from_secs(1)),
+                b"x",
+            ))
+            .unwrap_or_else(|err| panic!("{err}"));
+            assert!(KvJobStore::<MemStore>::decode::<Payload>(&data).is_ok());
+            for len in 0..data.len() {
+                assert!(KvJobStore::<MemStore>::decode::<Payload>(&data[..len]).is_err(), "{len}");
+            }
+
+            let mut tag = data.clone();
+            // Tag follows the length-prefixed id and target.
+            tag[4 + 1 + 4 + 6] = 3;
+            assert_eq!(
+                KvJobStore::<MemStore>::decode::<Payload>(&tag)
+                    .err()
+                    .as_deref(),
+                Some("unknown schedule type 3")
+            );
+
+            let mut trailing = data.clone();
+            trailing.push(0);
+            assert_eq!(
+                KvJobStore::<MemStore>::decode::<Payload>(&trailing)
+                    .err()
+                    .as_deref(),
+                Some("1 bytes of trailing data after the job")
+            );
+
+            // Interval follows the tag, and the next run time precedes the one-byte payload.
+            let mut interval = data.clone();
+            interval[16..28].copy_from_slice(&[0xFF; 12]);
+            assert_eq!(
+                KvJobStore::<MemStore>::decode::<Payload>(&interval)
+                    .err()
+                    .as_deref(),
+                Some("duration is out of range")
+            );
+            let mut next = data.clone();
+            let end = data.len() - 5;
+            next[end - 12..end - 4].copy_from_slice(&u64::MAX.to_le_bytes());
+            assert_eq!(
+                KvJobStore::<MemStore>::decode::<Payload>(&next)
+                    .err()
+                    .as_deref(),
+                Some("time is out of range")
+            );
+            let zero =
+                KvJobStore::<MemStore>::encode(&job("a", Schedule::Every(Duration::ZERO), b""))
+                    .unwrap_or_else(|err| panic!("{err}"));
+            assert_eq!(
+                KvJobStore::<MemStore>::decode::<Payload>(&zero)
+                    .err()
+                    .as_deref(),
+                Some("zero job interval")
+            );
+
+            let invalid =
+                KvJobStore::<MemStore>::encode(&job("a", Schedule::Once(UNIX_EPOCH), &[0xFF]))
+                    .unwrap_or_else(|err| panic!("{err}"));
+            assert_eq!(
+                KvJobStore::<MemStore>::decode::<Payload>(&invalid)
+                    .err()
+                    .as_deref(),
+                Some("invalid payload")
+            );
+            assert!(
+                KvJobStore::<MemStore>::encode(&job(
+                    "a",
+                    Schedule::Once(UNIX_EPOCH - Duration::from_secs(1)),
+                    b""
+                ))
+                .is_err()
+            );
+        }
+    }
+}
@@ -0,0 +1,396 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{UResult, UService};
+
+pub type KvPairs = Vec<(Vec<u8>, Vec<u8>)>;
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum KvOp {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+}
+
+/// Set of operations applied to a [`KvStore`] atomically.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct KvBatch(Vec<KvOp>);
+
+impl KvBatch {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn put(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> &mut Self {
+        self.0.push(KvOp::Put(key.into(), value.into()));
+        self
+    }
+
+    pub fn delete(&mut self, key: impl Into<Vec<u8>>) -> &mut Self {
+        self.0.push(KvOp::Delete(key.into()));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    pub fn len(&self) -> usize { self.0.len() }
+
+    pub fn ops(&self) -> &[KvOp] { &self.0 }
+}
+
+impl IntoIterator for KvBatch {
+    type Item = KvOp;
+    type IntoIter = std::vec::IntoIter<KvOp>;
+
+    fn into_iter(self) -> Self::IntoIter { self.0.into_iter() }
+}
+
+/// Persistent key-value storage for service state.
+///
+/// Keys and values are opaque byte strings; services encode their data with whatever encoding
+/// they use on the wire.
+pub trait KvStore: Send {
+    type Error: Display + Send + 'static;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error>;
+
+    /// Returns all key-value pairs with keys starting with `prefix`, ordered by key.
+    fn scan(&self, prefix: &[u8]) -> Result<KvPairs, Self::Error>;
+
+    /// Applies all operations in the batch atomically: either all of them are persisted, or
+    /// none.
+    fn commit(&mut self, batch: KvBatch) -> Result<(), Self::Error>;
+
+    fn contains(&self, key: &[u8]) -> Result<bool, Self::Error> {
+        self.get(key).map(|value| value.is_some())
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        let mut batch = KvBatch::new();
+        batch.put(key, value);
+        self.commit(batch)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Self::Error> {
+        let mut batch = KvBatch::new();
+        batch.delete(key);
+        self.commit(batch)
+    }
+}
+
+fn scan_map(map: &BTreeMap<Vec<u8>, Vec<u8>>, prefix: &[u8]) -> KvPairs {
+    map.range(prefix.to_vec()..)
+        .take_while(|(key, _)| key.starts_with(prefix))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+fn apply(map: &mut BTreeMap<Vec<u8>, Vec<u8>>, batch: KvBatch) {
+    for op in batch {
+        match op {
+            KvOp::Put(key, value) => {
+                map.insert(key, value);
+            }
+            KvOp::Delete(key) => {
+                map.remove(&key);
+            }
+        }
+    }
+}
+
+/// Non-persistent store keeping data in memory.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct MemStore(BTreeMap<Vec<u8>, Vec<u8>>);
+
+impl MemStore {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    pub fn len(&self) -> usize { self.0.len() }
+}
+
+impl KvStore for MemStore {
+    type Error = Infallible;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self.0.get(key).cloned())
+    }
+
+    fn scan(&self, prefix: &[u8]) -> Result<KvPairs, Self::Error> { Ok(scan_map(&self.0, prefix)) }
+
+    fn commit(&mut self, batch: KvBatch) -> Result<(), Self::Error> {
+        apply(&mut self.0, batch);
+        Ok(())
+    }
+}
+
+/// Store persisting all data to a single file.
+///
+/// The data are kept in memory; each commit rewrites the file to a temporary one and atomically
+/// renames it over the previous version. Suitable for small service state only.
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    data: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl FileStore {
+    /// Opens the store at `path`, creating an empty one if the file does not exist.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut data = BTreeMap::new();
+        match File::open(&path) {
+            Ok(file) => {
+                let mut reader = BufReader::new(file);
+                while let Some(key) = read_chunk(&mut reader, true)? {
+                    let value = read_chunk(&mut reader, false)?.expect("non-optional chunk");
+                    data.insert(key, value);
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        Ok(Self { path, data })
+    }
+
+    pub fn path(&self) -> &Path { &self.path }
+
+    fn persist(&self, data: &BTreeMap<Vec<u8>, Vec<u8>>) -> io::Result<()> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        for (key, value) in data {
+            write_chunk(&mut writer, key)?;
+            write_chunk(&mut writer, value)?;
+        }
+        writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+/// Reads a length-prefixed chunk. If the chunk is `optional`, the end of data before the chunk
+/// gives `None`; any other end of data is reported as [`io::ErrorKind::InvalidData`].
+///
+/// The chunk is read incrementally, so a corrupted length can't make it allocate more memory than
+/// the remaining data take.
+pub(crate) fn read_chunk(reader: &mut impl Read, optional: bool) -> io::Result<Option<Vec<u8>>> {
+    let truncated = || io::Error::new(io::ErrorKind::InvalidData, "truncated data chunk");
+    let mut len = [0u8; 4];
+    let mut read = 0;
+    while read < len.len() {
+        match reader.read(&mut len[read..]) {
+            Ok(0) if read == 0 && optional => return Ok(None),
+            Ok(0) => return Err(truncated()),
+            Ok(count) => read += count,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    let mut chunk = vec![];
+    reader.take(len as u64).read_to_end(&mut chunk)?;
+    if chunk.len() != len {
+        return Err(truncated());
+    }
+    Ok(Some(chunk))
+}
+
+pub(crate) fn write_chunk(writer: &mut impl Write, chunk: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(chunk.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "data chunk exceeds 4GB"))?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(chunk)
+}
+
+impl KvStore for FileStore {
+    type Error = io::Error;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self.data.get(key).cloned())
+    }
+
+    fn scan(&self, prefix: &[u8]) -> Result<KvPairs, Self::Error> {
+        Ok(scan_map(&self.data, prefix))
+    }
+
+    fn commit(&mut self, batch: KvBatch) -> Result<(), Self::Error> {
+        let mut data = self.data.clone();
+        apply(&mut data, batch);
+        self.persist(&data)?;
+        self.data = data;
+        Ok(())
+    }
+}
+
+/// Store which can be shared between services running in different threads.
+#[derive(Debug, Default)]
+pub struct SharedStore<K: KvStore>(Arc<Mutex<K>>);
+
+impl<K: KvStore> Clone for SharedStore<K> {
+    fn clone(&self) -> Self { Self(self.0.clone()) }
+}
+
+impl<K: KvStore> SharedStore<K> {
+    pub fn new(store: K) -> Self { Self(Arc::new(Mutex::new(store))) }
+
+    /// Locks the store for exclusive access, for instance to perform reads and writes which must
+    /// not interleave with other users of the store.
+    pub fn lock(&self) -> MutexGuard<'_, K> { self.0.lock().unwrap_or_else(|err| err.into_inner()) }
+}
+
+impl<K: KvStore> KvStore for SharedStore<K> {
+    type Error = K::Error;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> { self.lock().get(key) }
+
+    fn scan(&self, prefix: &[u8]) -> Result<KvPairs, Self::Error> { self.lock().scan(prefix) }
+
+    fn commit(&mut self, batch: KvBatch) -> Result<(), Self::Error> { self.lock().commit(batch) }
+}
+
+/// Service which can save its state into a [`KvStore`] and restore from it.
+pub trait USnapshot: UService {
+    fn snapshot(&self) -> Vec<u8>;
+
+    fn restore(&mut self, snapshot: &[u8]) -> Result<(), Self::Error>;
+
+    /// Key under which the service snapshot is stored.
+    fn snapshot_key() -> Vec<u8> { format!("snapshot/{}", Self::NAME).into_bytes() }
+
+    fn save_snapshot<K: KvStore>(&self, store: &mut K) -> Result<(), K::Error> {
+        store.put(&Self::snapshot_key(), &self.snapshot())
+    }
+
+    /// Restores the service state from the store. Returns `false` if the store contains no
+    /// snapshot for the service.
+    fn load_snapshot<K: KvStore>(&mut self, store: &K) -> UResult<bool>
+    where Self::Error: Send + 'static {
+        let Some(snapshot) = store
+            .get(&Self::snapshot_key())
+            .map_err(|err| Box::new(err) as _)?
+        else {
+            return Ok(false);
+        };
+        self.restore(&snapshot).map_err(|err| Box::new(err) as _)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process;
+
+    use super::*;
+
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("microservices-store-{}-{name}", process::id()));
+            let _ = fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) { let _ = fs::remove_file(&self.0); }
+    }
+
+    fn open(path: &TempPath) -> io::Result<FileStore> { FileStore::open(&path.0) }
+
+    #[test]
+    fn round_trip() {
+        let path = TempPath::new("round-trip");
+        let mut store = open(&path).unwrap();
+        assert_eq!(store.get(b"a").unwrap(), None);
+
+        let mut batch = KvBatch::new();
+        batch
+            .put(b"a/1".as_slice(), b"one".as_slice())
+            .put(b"a/2".as_slice(), vec![])
+            .put(b"b".as_slice(), vec![0xFF; 1000])
+            .put(b"c".as_slice(), b"deleted".as_slice())
+            .delete(b"c".as_slice());
+        store.commit(batch).unwrap();
+        store.put(b"", b"empty key").unwrap();
+
+        let store = open(&path).unwrap();
+        assert_eq!(store.get(b"a/2").unwrap(), Some(vec![]));
+        assert_eq!(store.get(b"b").unwrap(), Some(vec![0xFF; 1000]));
+        assert_eq!(store.get(b"").unwrap(), Some(b"empty key".to_vec()));
+        assert!(!store.contains(b"c").unwrap());
+        assert_eq!(store.scan(b"a/").unwrap(), vec![
+            (b"a/1".to_vec(), b"one".to_vec()),
+            (b"a/2".to_vec(), vec![])
+        ]);
+
+        let mut store = store;
+        store.delete(b"b").unwrap();
+        assert_eq!(open(&path).unwrap().scan(b"").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn corrupted() {
+        let path = TempPath::new("corrupted");
+        let mut store = open(&path).unwrap();
+        store.put(b"key", b"value").unwrap();
+        let data = fs::read(&path.0).unwrap();
+
+        // Any truncation except at a record boundary is rejected.
+        for len in 1..data.len() {
+            fs::write(&path.0, &data[..len]).unwrap();
+            let err = open(&path).expect_err("truncated file");
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{len}");
+        }
+        fs::write(&path.0, b"").unwrap();
+        assert!(open(&path).unwrap().scan(b"").unwrap().is_empty());
+
+        // A corrupted length doesn't make the store allocate beyond the file size.
+        let mut huge = data.clone();
+        huge[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path.0, &huge).unwrap();
+        assert_eq!(open(&path).expect_err("corrupted length").kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn chunks() {
+        let mut data = vec![];
+        write_chunk(&mut data, b"abc").unwrap();
+        write_chunk(&mut data, b"").unwrap();
+        let reader = &mut data.as_slice();
+        assert_eq!(read_chunk(reader, false).unwrap(), Some(b"abc".to_vec()));
+        assert_eq!(read_chunk(reader, true).unwrap(), Some(vec![]));
+        assert_eq!(read_chunk(reader, true).unwrap(), None);
+        let err = read_chunk(reader, false).expect_err("missing chunk");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
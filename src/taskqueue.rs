@@ -0,0 +1,428 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{RecvTimeoutError, SendError};
+
+use crate::uclock::{Clock, SystemClock};
+use crate::uservice::UMsg;
+use crate::{USender, UService};
+
+/// Task leased to a worker.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Task<T> {
+    pub id: u64,
+    /// Number of times the task was leased, including the current lease.
+    pub attempt: u32,
+    pub payload: T,
+}
+
+#[derive(Clone, Debug)]
+pub enum TaskMsg<T> {
+    Enqueue(T),
+    /// Leases the next task to the worker, sending it to the worker as soon as a task is
+    /// available.
+    Lease(USender<Task<T>>),
+    /// Completes the task leased under the given attempt.
+    Ack {
+        id: u64,
+        attempt: u32,
+    },
+    /// Returns the task leased under the given attempt to the queue, to be retried.
+    Nack {
+        id: u64,
+        attempt: u32,
+    },
+}
+
+/// Service distributing tasks between workers.
+///
+/// A leased task is hidden from other workers for the `visibility` timeout. If the worker does
+/// not acknowledge the task in time, or rejects it, the task is leased again; a task which has
+/// failed `max_attempts` times is moved to the dead letters. Tasks are delivered at least once,
+/// so their processing must be idempotent.
+///
+/// Expired leases are checked on each message and tick, so the queue must be spawned with ticks,
+/// which define the precision of the visibility timeout when no messages arrive.
+#[derive(Debug)]
+pub struct TaskQueue<T> {
+    clock: Box<dyn Clock>,
+    visibility: Duration,
+    max_attempts: u32,
+    next_id: u64,
+    queue: VecDeque<Task<T>>,
+    /// Leased tasks with the time they were leased at.
+    leased: BTreeMap<u64, (Instant, Task<T>)>,
+    /// Workers waiting for a task, in the order of their requests.
+    workers: VecDeque<USender<Task<T>>>,
+    dead: Vec<Task<T>>,
+    dead_letters: Option<USender<Task<T>>>,
+}
+
+impl<T: Clone + Send + 'static> TaskQueue<T> {
+    pub fn new(visibility: Duration, max_attempts: u32) -> Self {
+        Self::with_clock(visibility, max_attempts, SystemClock)
+    }
+
+    /// Creates queue measuring the visibility timeout with the `clock`.
+    pub fn with_clock(visibility: Duration, max_attempts: u32, clock: impl Clock) -> Self {
+        Self {
+            clock: Box::new(clock),
+            visibility,
+            max_attempts: max_attempts.max(1),
+            next_id: 0,
+            queue: VecDeque::new(),
+            leased: BTreeMap::new(),
+            workers: VecDeque::new(),
+            dead: vec![],
+            dead_letters: None,
+        }
+    }
+
+    /// Sends the dead letters to the `sender` instead of keeping them in the queue.
+    pub fn with_dead_letters(mut self, sender: USender<Task<T>>) -> Self {
+        self.dead_letters = Some(sender);
+        self
+    }
+
+    /// Number of tasks waiting to be leased.
+    pub fn queued(&self) -> usize { self.queue.len() }
+
+    /// Number of tasks leased to the workers and not yet acknowledged.
+    pub fn leased(&self) -> usize { self.leased.len() }
+
+    /// Number of workers waiting for a task.
+    pub fn waiting(&self) -> usize { self.workers.len() }
+
+    /// Tasks which have failed all their attempts and were not sent to the dead letter
+    /// recipient.
+    pub fn dead_letters(&self) -> &[Task<T>] { &self.dead }
+
+    /// Adds the task to the queue, returning its id.
+    pub fn enqueue(&mut self, payload: T) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.queue.push_back(Task { id, attempt: 0, payload });
+        self.dispatch();
+        id
+    }
+
+    /// Leases the next available task to the worker, or makes it wait for one.
+    pub fn lease(&mut self, worker: USender<Task<T>>) {
+        self.workers.push_back(worker);
+        self.dispatch();
+    }
+
+    pub fn ack(&mut self, id: u64, attempt: u32) -> Result<(), String> {
+        self.release(id, attempt).map(|_| ())
+    }
+
+    pub fn nack(&mut self, id: u64, attempt: u32) -> Result<(), String> {
+        let task = self.release(id, attempt)?;
+        self.retry(task);
+        self.dispatch();
+        Ok(())
+    }
+
+    /// Returns the tasks whose visibility timeout has elapsed to the queue.
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        let expired = self
+            .leased
+            .iter()
+            .filter(|(_, (since, _))| now.saturating_duration_since(*since) >= self.visibility)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in expired {
+            let (_, task) = self.leased.remove(&id).expect("task is leased");
+            #[cfg(feature = "log")]
+            log::debug!(
+                target: Self::NAME,
+                "lease of task {id} attempt {} has expired",
+                task.attempt
+            );
+            self.retry(task);
+        }
+        self.dispatch();
+    }
+
+    fn release(&mut self, id: u64, attempt: u32) -> Result<Task<T>, String> {
+        match self.leased.get(&id) {
+            Some((_, task)) if task.attempt == attempt => {
+                Ok(self.leased.remove(&id).expect("task is leased").1)
+            }
+            _ => Err(format!("task {id} is not leased under attempt {attempt}")),
+        }
+    }
+
+    fn retry(&mut self, task: Task<T>) {
+        if task.attempt < self.max_attempts {
+            self.queue.push_back(task);
+            return;
+        }
+        self.error(
+            &format!("task {}", task.id),
+            format_args!("failed {} attempt(s)", task.attempt),
+        );
+        match &self.dead_letters {
+            Some(sender) => {
+                if let Err(SendError(task)) = sender.send(task) {
+                    self.dead.push(task);
+                }
+            }
+            None => self.dead.push(task),
+        }
+    }
+
+    fn dispatch(&mut self) {
+        while !self.queue.is_empty() {
+            let Some(worker) = self.workers.pop_front() else {
+                break;
+            };
+            let mut task = self.queue.pop_front().expect("queue is not empty");
+            task.attempt += 1;
+            // A worker which has stopped waiting has dropped the receiver.
+            if worker.try_send(task.clone()).is_err() {
+                task.attempt -= 1;
+                self.queue.push_front(task);
+                continue;
+            }
+            self.leased.insert(task.id, (self.clock.now(), task));
+        }
+    }
+}
+
+impl<T: Clone + Send + 'static> UService for TaskQueue<T> {
+    type Msg = TaskMsg<T>;
+    type Error = String;
+    const NAME: &'static str = "tasks";
+
+    fn tick(&mut self) -> Result<(), Self::Error> {
+        self.expire();
+        Ok(())
+    }
+
+    fn process(&mut self, msg: Self::Msg) -> Result<ControlFlow<u8>, Self::Error> {
+        // Ticks don't happen while messages keep arriving, so the leases are checked here too.
+        self.expire();
+        match msg {
+            TaskMsg::Enqueue(payload) => {
+                self.enqueue(payload);
+            }
+            TaskMsg::Lease(worker) => self.lease(worker),
+            TaskMsg::Ack { id, attempt } => self.ack(id, attempt)?,
+            TaskMsg::Nack { id, attempt } => self.nack(id, attempt)?,
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn terminate(&mut self) {}
+}
+
+/// Client of a [`TaskQueue`] service, used by the task producers and workers.
+#[derive(Clone, Debug)]
+pub struct TaskClient<T>(USender<TaskMsg<T>>);
+
+impl<T> From<USender<TaskMsg<T>>> for TaskClient<T> {
+    fn from(sender: USender<TaskMsg<T>>) -> Self { Self(sender) }
+}
+
+impl<T: Send> TaskClient<T> {
+    pub fn new(queue: USender<TaskMsg<T>>) -> Self { Self(queue) }
+
+    pub fn enqueue(&self, payload: T) -> Result<(), SendError<TaskMsg<T>>> {
+        self.0.send(TaskMsg::Enqueue(payload))
+    }
+
+    /// Waits for the next task for at most `timeout`.
+    ///
+    /// Returns [`RecvTimeoutError::Disconnected`] if the queue has stopped. A task leased right
+    /// when the waiting times out is lost to the worker and is leased again after the visibility
+    /// timeout.
+    pub fn lease(&self, timeout: Duration) -> Result<Task<T>, RecvTimeoutError> {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        // On failure the sender is dropped, so the receiver reports the disconnection.
+        let _ = self.0.send(TaskMsg::Lease(USender(sender)));
+        match receiver.recv_timeout(timeout)? {
+            UMsg::Msg(task) => Ok(task),
+            UMsg::Terminate => Err(RecvTimeoutError::Disconnected),
+        }
+    }
+
+    pub fn ack(&self, task: &Task<T>) -> Result<(), SendError<TaskMsg<T>>> {
+        self.0
+            .send(TaskMsg::Ack { id: task.id, attempt: task.attempt })
+    }
+
+    pub fn nack(&self, task: &Task<T>) -> Result<(), SendError<TaskMsg<T>>> {
+        self.0
+            .send(TaskMsg::Nack { id: task.id, attempt: task.attempt })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{MockClock, TestExecutor, UProbe, UThread};
+
+    const VISIBILITY: Duration = Duration::from_secs(10);
+
+    fn executor(max_attempts: u32) -> TestExecutor<TaskQueue<u32>> {
+        let clock = MockClock::new();
+        let queue = TaskQueue::with_clock(VISIBILITY, max_attempts, clock.clone());
+        TestExecutor::with_clock(queue, Some(Duration::from_secs(1)), clock)
+    }
+
+    fn lease(executor: &mut TestExecutor<TaskQueue<u32>>) -> UProbe<Task<u32>> {
+        let worker = UProbe::new();
+        executor.deliver(TaskMsg::Lease(worker.sender()));
+        worker
+    }
+
+    fn task(id: u64, attempt: u32, payload: u32) -> Task<u32> { Task { id, attempt, payload } }
+
+    #[test]
+    fn lease_and_ack() {
+        let mut executor = executor(3);
+        executor.deliver(TaskMsg::Enqueue(10));
+        executor.deliver(TaskMsg::Enqueue(20));
+        let worker = lease(&mut executor);
+        assert_eq!(worker.drain(), [task(0, 1, 10)]);
+        assert_eq!(executor.service().queued(), 1);
+        assert_eq!(executor.service().leased(), 1);
+
+        executor.deliver(TaskMsg::Ack { id: 0, attempt: 1 });
+        assert_eq!(executor.service().leased(), 0);
+        let worker = lease(&mut executor);
+        assert_eq!(worker.drain(), [task(1, 1, 20)]);
+        assert!(executor.errors().is_empty());
+    }
+
+    #[test]
+    fn waiting_workers() {
+        let mut executor = executor(3);
+        let first = lease(&mut executor);
+        let second = lease(&mut executor);
+        assert_eq!(executor.service().waiting(), 2);
+        executor.deliver(TaskMsg::Enqueue(10));
+        executor.deliver(TaskMsg::Enqueue(20));
+        assert_eq!(first.drain(), [task(0, 1, 10)]);
+        assert_eq!(second.drain(), [task(1, 1, 20)]);
+        assert_eq!(executor.service().waiting(), 0);
+    }
+
+    #[test]
+    fn disconnected_worker_skipped() {
+        let mut executor = executor(3);
+        drop(lease(&mut executor));
+        let worker = lease(&mut executor);
+        executor.deliver(TaskMsg::Enqueue(10));
+        assert_eq!(worker.drain(), [task(0, 1, 10)]);
+        assert_eq!(executor.service().waiting(), 0);
+    }
+
+    #[test]
+    fn visibility_timeout() {
+        let mut executor = executor(3);
+        executor.deliver(TaskMsg::Enqueue(10));
+        let first = lease(&mut executor);
+        assert_eq!(first.drain(), [task(0, 1, 10)]);
+        let second = lease(&mut executor);
+
+        executor.advance(VISIBILITY - Duration::from_secs(1));
+        assert!(second.is_empty());
+        executor.advance(Duration::from_secs(1));
+        assert_eq!(second.drain(), [task(0, 2, 10)]);
+
+        // The first lease has expired, so its acknowledgement is stale.
+        executor.deliver(TaskMsg::Ack { id: 0, attempt: 1 });
+        let errors = executor.take_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "task 0 is not leased under attempt 1");
+        assert_eq!(executor.service().leased(), 1);
+        executor.deliver(TaskMsg::Ack { id: 0, attempt: 2 });
+        assert_eq!(executor.service().leased(), 0);
+        assert!(executor.errors().is_empty());
+    }
+
+    #[test]
+    fn nack_retried() {
+        let mut executor = executor(3);
+        executor.deliver(TaskMsg::Enqueue(10));
+        executor.deliver(TaskMsg::Enqueue(20));
+        let worker = lease(&mut executor);
+        assert_eq!(worker.drain(), [task(0, 1, 10)]);
+        executor.deliver(TaskMsg::Nack { id: 0, attempt: 1 });
+        // The rejected task is retried after the tasks queued before.
+        assert_eq!(lease(&mut executor).drain(), [task(1, 1, 20)]);
+        assert_eq!(lease(&mut executor).drain(), [task(0, 2, 10)]);
+    }
+
+    #[test]
+    fn dead_letters() {
+        let mut executor = executor(2);
+        executor.deliver(TaskMsg::Enqueue(10));
+        for attempt in 1..=2 {
+            assert_eq!(lease(&mut executor).drain(), [task(0, attempt, 10)]);
+            executor.deliver(TaskMsg::Nack { id: 0, attempt });
+        }
+        assert_eq!(executor.service().dead_letters(), [task(0, 2, 10)]);
+        assert_eq!(executor.service().queued(), 0);
+        assert_eq!(executor.service().leased(), 0);
+
+        let dead = UProbe::new();
+        let clock = MockClock::new();
+        let queue =
+            TaskQueue::with_clock(VISIBILITY, 1, clock.clone()).with_dead_letters(dead.sender());
+        let mut executor = TestExecutor::with_clock(queue, Some(Duration::from_secs(1)), clock);
+        executor.deliver(TaskMsg::Enqueue(10));
+        assert_eq!(lease(&mut executor).drain(), [task(0, 1, 10)]);
+        executor.advance(VISIBILITY);
+        assert_eq!(dead.drain(), [task(0, 1, 10)]);
+        assert!(executor.service().dead_letters().is_empty());
+    }
+
+    #[test]
+    fn client() {
+        let queue = TaskQueue::new(Duration::from_secs(10), 3);
+        let thread = UThread::new(queue, Some(Duration::from_millis(10)));
+        let client = TaskClient::new(thread.sender());
+        assert_eq!(client.lease(Duration::from_millis(10)), Err(RecvTimeoutError::Timeout));
+
+        client.enqueue(10).unwrap_or_else(|_| panic!());
+        let task = client
+            .lease(Duration::from_secs(1))
+            .unwrap_or_else(|err| panic!("{err}"));
+        assert_eq!(task, Task { id: 0, attempt: 1, payload: 10 });
+        client.nack(&task).unwrap_or_else(|_| panic!());
+        let task = client
+            .lease(Duration::from_secs(1))
+            .unwrap_or_else(|err| panic!("{err}"));
+        assert_eq!(task.attempt, 2);
+        client.ack(&task).unwrap_or_else(|_| panic!());
+
+        drop(thread);
+        assert_eq!(client.lease(Duration::from_secs(1)), Err(RecvTimeoutError::Disconnected));
+    }
+}
@@ -0,0 +1,205 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{Receiver, Sender};
+
+/// Source of time for service event loops.
+pub trait Clock: Debug + Send + Sync + 'static {
+    fn now(&self) -> Instant;
+
+    /// Returns a channel which delivers the current time once the clock reaches the `deadline`.
+    fn at(&self, deadline: Instant) -> Receiver<Instant>;
+
+    fn sleep_until(&self, deadline: Instant) {
+        // The sender is dropped only after it has delivered the time.
+        let _ = self.at(deadline).recv();
+    }
+}
+
+/// Clock using the system monotonic time.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant { Instant::now() }
+
+    fn at(&self, deadline: Instant) -> Receiver<Instant> { crossbeam_channel::at(deadline) }
+
+    fn sleep_until(&self, deadline: Instant) {
+        let now = Instant::now();
+        if deadline > now {
+            std::thread::sleep(deadline - now);
+        }
+    }
+}
+
+/// Clock which advances only when told to, for testing time-dependent behaviour without
+/// sleeping. Clones share the same time.
+#[derive(Clone, Debug)]
+pub struct MockClock(Arc<Mutex<MockTime>>);
+
+#[derive(Debug)]
+struct MockTime {
+    start: Instant,
+    now: Instant,
+    timers: Vec<(Instant, Sender<Instant>)>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self { Self::new() }
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self(Arc::new(Mutex::new(MockTime { start: now, now, timers: vec![] })))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockTime> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Time by which the clock was advanced since its creation.
+    pub fn elapsed(&self) -> Duration {
+        let time = self.lock();
+        time.now - time.start
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let deadline = self.now() + by;
+        self.advance_to(deadline);
+    }
+
+    /// Moves the clock forward to the `deadline`, firing all timers which expire by then. Does
+    /// nothing if the clock is already past the deadline.
+    pub fn advance_to(&self, deadline: Instant) {
+        let mut time = self.lock();
+        if deadline <= time.now {
+            return;
+        }
+        time.now = deadline;
+        time.timers.retain(|(at, sender)| {
+            if *at > deadline {
+                return true;
+            }
+            let _ = sender.send(deadline);
+            false
+        });
+    }
+
+    /// Number of timers which are waiting for the clock to advance.
+    pub fn pending_timers(&self) -> usize { self.lock().timers.len() }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant { self.lock().now }
+
+    fn at(&self, deadline: Instant) -> Receiver<Instant> {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        let mut time = self.lock();
+        if deadline <= time.now {
+            let _ = sender.send(time.now);
+        } else {
+            time.timers.push((deadline, sender));
+        }
+        receiver
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn advance() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(clock.elapsed(), Duration::from_secs(2));
+        assert_eq!(clock.now(), start + Duration::from_secs(2));
+
+        // The clock never goes back.
+        clock.advance_to(start + Duration::from_secs(1));
+        assert_eq!(clock.elapsed(), Duration::from_secs(2));
+
+        let clone = clock.clone();
+        clone.advance(Duration::from_secs(1));
+        assert_eq!(clock.elapsed(), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn timers() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let past = clock.at(start);
+        assert_eq!(past.try_recv(), Ok(start));
+
+        let first = clock.at(start + Duration::from_secs(1));
+        let second = clock.at(start + Duration::from_secs(3));
+        assert_eq!(clock.pending_timers(), 2);
+        assert!(first.try_recv().is_err());
+
+        // Timers get the time the clock was advanced to, not their deadline.
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(first.try_recv(), Ok(start + Duration::from_secs(2)));
+        assert!(second.try_recv().is_err());
+        assert_eq!(clock.pending_timers(), 1);
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(second.try_recv(), Ok(start + Duration::from_secs(3)));
+        assert_eq!(clock.pending_timers(), 0);
+    }
+
+    #[test]
+    fn sleep_until() {
+        let clock = MockClock::new();
+        let deadline = clock.now() + Duration::from_secs(60);
+        let sleeper = clock.clone();
+        let sleeping = thread::spawn(move || {
+            sleeper.sleep_until(deadline);
+            sleeper.now()
+        });
+        while clock.pending_timers() == 0 {
+            thread::yield_now();
+        }
+        assert!(!sleeping.is_finished());
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(sleeping.join().ok(), Some(deadline));
+
+        // Sleeping until a past deadline returns immediately.
+        clock.sleep_until(deadline);
+    }
+
+    #[test]
+    fn system_clock() {
+        let clock = SystemClock;
+        let deadline = clock.now() + Duration::from_millis(10);
+        clock.sleep_until(deadline);
+        assert!(clock.now() >= deadline);
+        assert!(clock.at(deadline).recv().is_ok());
+    }
+}
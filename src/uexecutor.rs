@@ -0,0 +1,364 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Display;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{Receiver, Sender, TryRecvError};
+
+use crate::uservice::UMsg;
+use crate::{Clock, MockClock, UFailure, UFailureKind, USender, UService};
+
+/// Runs a service on the current thread, letting tests deliver messages and advance virtual time
+/// to trigger [`UService::tick`] deterministically.
+///
+/// Messages are processed only when the test calls [`TestExecutor::run_until_idle`] or
+/// [`TestExecutor::advance`]; ticks happen each time the virtual time advances by the tick
+/// interval past the last processed message or tick, mirroring [`crate::UThread`]. Virtual time
+/// is kept by a [`MockClock`], which may be shared with other executors and with the service
+/// itself.
+#[derive(Debug)]
+pub struct TestExecutor<S: UService> {
+    service: S,
+    sender: Sender<UMsg<S::Msg>>,
+    receiver: Receiver<UMsg<S::Msg>>,
+    ticks: Option<Duration>,
+    clock: MockClock,
+    last_activity: Instant,
+    exit_code: Option<u8>,
+    errors: Vec<UFailure>,
+}
+
+impl<S: UService> TestExecutor<S> {
+    pub fn new(service: S, ticks: Option<Duration>) -> Self {
+        Self::with_clock(service, ticks, MockClock::new())
+    }
+
+    pub fn with_clock(mut service: S, ticks: Option<Duration>, clock: MockClock) -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        service.set_self_sender(USender(sender.clone()));
+        Self {
+            service,
+            sender,
+            receiver,
+            ticks,
+            last_activity: clock.now(),
+            clock,
+            exit_code: None,
+            errors: vec![],
+        }
+    }
+
+    pub fn sender(&self) -> USender<S::Msg> { USender(self.sender.clone()) }
+
+    pub fn service(&self) -> &S { &self.service }
+
+    pub fn service_mut(&mut self) -> &mut S { &mut self.service }
+
+    pub fn clock(&self) -> &MockClock { &self.clock }
+
+    /// Virtual time elapsed since the clock was created.
+    pub fn now(&self) -> Duration { self.clock.elapsed() }
+
+    pub fn pending(&self) -> usize { self.receiver.len() }
+
+    /// Errors returned by the service from message processing and ticks, in order.
+    pub fn errors(&self) -> &[UFailure] { &self.errors }
+
+    pub fn take_errors(&mut self) -> Vec<UFailure> { std::mem::take(&mut self.errors) }
+
+    /// Status code with which the service has terminated, if it did.
+    pub fn exit_code(&self) -> Option<u8> { self.exit_code }
+
+    pub fn is_terminated(&self) -> bool { self.exit_code.is_some() }
+
+    /// Queues a message to the service without processing it.
+    pub fn send(&self, msg: S::Msg) {
+        self.sender
+            .send(UMsg::Msg(msg))
+            .unwrap_or_else(|_| unreachable!("the executor holds the receiver"));
+    }
+
+    /// Queues a message and processes it together with all other pending messages.
+    pub fn deliver(&mut self, msg: S::Msg) -> usize {
+        self.send(msg);
+        self.run_until_idle()
+    }
+
+    /// Processes pending messages, including those the service sends to itself while
+    /// processing, until the mailbox is empty or the service terminates. Returns the number of
+    /// processed messages.
+    pub fn run_until_idle(&mut self) -> usize {
+        let mut count = 0;
+        while !self.is_terminated() {
+            let msg = match self.receiver.try_recv() {
+                Ok(UMsg::Msg(msg)) => msg,
+                Ok(UMsg::Terminate) => {
+                    self.terminate();
+                    break;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    unreachable!("the executor holds the sender")
+                }
+            };
+            count += 1;
+            self.last_activity = self.clock.now();
+            match self.service.process(msg) {
+                Err(err) => self.report(UFailureKind::Process, err),
+                Ok(ControlFlow::Break(code)) => {
+                    self.service.terminate();
+                    self.exit_code = Some(code);
+                }
+                Ok(ControlFlow::Continue(())) => {}
+            }
+        }
+        count
+    }
+
+    /// Advances virtual time, processing pending messages and calling [`UService::tick`] each
+    /// time the tick interval elapses without activity. Returns the number of ticks.
+    pub fn advance(&mut self, by: Duration) -> usize {
+        let target = self.clock.now() + by;
+        let mut count = 0;
+        loop {
+            self.run_until_idle();
+            let Some(ticks) = self.ticks else { break };
+            let next = self.last_activity + ticks;
+            if self.is_terminated() || next > target {
+                break;
+            }
+            self.clock.advance_to(next);
+            self.last_activity = self.clock.now();
+            count += 1;
+            if let Err(err) = self.service.tick() {
+                self.report(UFailureKind::Tick, err);
+            }
+        }
+        self.clock.advance_to(target);
+        count
+    }
+
+    /// Terminates the service the same way as dropping its [`crate::UThread`] does.
+    pub fn terminate(&mut self) {
+        if self.is_terminated() {
+            return;
+        }
+        self.service.terminate();
+        self.exit_code = Some(0);
+    }
+
+    fn report(&mut self, kind: UFailureKind, err: impl Display) {
+        let failure = UFailure::new(kind, err).with_service(S::NAME);
+        self.service.failure(failure.clone());
+        self.errors.push(failure);
+    }
+}
+
+/// Receiving end for messages a service under test sends to other services.
+#[derive(Debug)]
+pub struct UProbe<Msg> {
+    sender: Sender<UMsg<Msg>>,
+    receiver: Receiver<UMsg<Msg>>,
+}
+
+impl<Msg> Default for UProbe<Msg> {
+    fn default() -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        Self { sender, receiver }
+    }
+}
+
+impl<Msg> UProbe<Msg> {
+    pub fn new() -> Self { Self::default() }
+
+    /// Sender to give to the service under test in place of a real service sender.
+    pub fn sender(&self) -> USender<Msg> { USender(self.sender.clone()) }
+
+    pub fn try_recv(&self) -> Option<Msg> {
+        match self.receiver.try_recv() {
+            Ok(UMsg::Msg(msg)) => Some(msg),
+            Ok(UMsg::Terminate) | Err(_) => None,
+        }
+    }
+
+    /// Takes all messages received by the probe so far.
+    pub fn drain(&self) -> Vec<Msg> { std::iter::from_fn(|| self.try_recv()).collect() }
+
+    pub fn is_empty(&self) -> bool { self.receiver.is_empty() }
+
+    pub fn len(&self) -> usize { self.receiver.len() }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Counter {
+        msgs: Vec<u32>,
+        ticks: usize,
+        failures: Cell<usize>,
+        terminated: bool,
+        self_sender: Option<USender<u32>>,
+    }
+
+    impl UService for Counter {
+        type Msg = u32;
+        type Error = String;
+        const NAME: &'static str = "counter";
+
+        fn tick(&mut self) -> Result<(), Self::Error> {
+            self.ticks += 1;
+            if self.ticks == 2 { Err("second tick".to_owned()) } else { Ok(()) }
+        }
+
+        fn process(&mut self, msg: u32) -> Result<ControlFlow<u8>, Self::Error> {
+            self.msgs.push(msg);
+            match msg {
+                0 => Err("zero".to_owned()),
+                // Resends itself decremented, down to nine.
+                10..=19 => {
+                    if let Some(sender) = &self.self_sender {
+                        let _ = sender.send(msg - 1);
+                    }
+                    Ok(ControlFlow::Continue(()))
+                }
+                100.. => Ok(ControlFlow::Break((msg - 100) as u8)),
+                _ => Ok(ControlFlow::Continue(())),
+            }
+        }
+
+        fn set_self_sender(&mut self, sender: USender<u32>) { self.self_sender = Some(sender); }
+
+        fn failure(&self, _: UFailure) { self.failures.set(self.failures.get() + 1); }
+
+        fn terminate(&mut self) { self.terminated = true; }
+    }
+
+    #[test]
+    fn ticks_under_advance() {
+        let mut executor = TestExecutor::new(Counter::default(), Some(Duration::from_secs(1)));
+        assert_eq!(executor.advance(Duration::from_millis(999)), 0);
+        assert_eq!(executor.advance(Duration::from_millis(1)), 1);
+        assert_eq!(executor.advance(Duration::from_millis(3500)), 3);
+        assert_eq!(executor.now(), Duration::from_millis(4500));
+
+        // A message postpones the next tick by the full interval.
+        executor.send(1);
+        assert_eq!(executor.advance(Duration::from_millis(999)), 0);
+        assert_eq!(executor.advance(Duration::from_millis(1)), 1);
+        assert_eq!(executor.service().ticks, 5);
+        assert_eq!(executor.service().msgs, vec![1]);
+    }
+
+    #[test]
+    fn no_ticks_without_interval() {
+        let mut executor = TestExecutor::new(Counter::default(), None);
+        executor.send(1);
+        assert_eq!(executor.advance(Duration::from_secs(60)), 0);
+        assert_eq!(executor.service().msgs, vec![1]);
+        assert_eq!(executor.now(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn shared_clock() {
+        let clock = MockClock::new();
+        let mut first = TestExecutor::with_clock(
+            Counter::default(),
+            Some(Duration::from_secs(1)),
+            clock.clone(),
+        );
+        let mut second =
+            TestExecutor::with_clock(Counter::default(), Some(Duration::from_secs(2)), clock);
+        assert_eq!(first.advance(Duration::from_secs(4)), 4);
+        assert_eq!(second.now(), Duration::from_secs(4));
+        // Ticks missed while the clock was advanced by another executor are coalesced, as they
+        // are in `UThread`.
+        assert_eq!(second.advance(Duration::ZERO), 1);
+        assert_eq!(second.advance(Duration::from_secs(2)), 1);
+    }
+
+    #[test]
+    fn run_until_idle_self_messages() {
+        let mut executor = TestExecutor::new(Counter::default(), None);
+        executor.send(2);
+        executor.send(3);
+        assert_eq!(executor.pending(), 2);
+        assert_eq!(executor.run_until_idle(), 2);
+        assert_eq!(executor.deliver(12), 4);
+        assert_eq!(executor.service().msgs, vec![2, 3, 12, 11, 10, 9]);
+        assert_eq!(executor.pending(), 0);
+    }
+
+    #[test]
+    fn errors_reported() {
+        let mut executor = TestExecutor::new(Counter::default(), Some(Duration::from_secs(1)));
+        executor.deliver(0);
+        executor.advance(Duration::from_secs(2));
+        let kinds = executor
+            .errors()
+            .iter()
+            .map(|failure| failure.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, vec![UFailureKind::Process, UFailureKind::Tick]);
+        assert_eq!(executor.service().failures.get(), 2);
+        assert_eq!(executor.take_errors().len(), 2);
+        assert!(executor.errors().is_empty());
+    }
+
+    #[test]
+    fn exit_code() {
+        let mut executor = TestExecutor::new(Counter::default(), Some(Duration::from_secs(1)));
+        executor.send(105);
+        executor.send(1);
+        assert_eq!(executor.run_until_idle(), 1);
+        assert_eq!(executor.exit_code(), Some(5));
+        assert!(executor.service().terminated);
+        assert_eq!(executor.advance(Duration::from_secs(5)), 0);
+        assert_eq!(executor.service().msgs, vec![105]);
+    }
+
+    #[test]
+    fn terminate() {
+        let mut executor = TestExecutor::new(Counter::default(), None);
+        executor.terminate();
+        assert_eq!(executor.exit_code(), Some(0));
+        assert!(executor.service().terminated);
+        assert_eq!(executor.deliver(1), 0);
+    }
+
+    #[test]
+    fn probe() {
+        let probe = UProbe::new();
+        assert!(probe.is_empty());
+        let sender = probe.sender();
+        let _ = sender.send(1u32);
+        let _ = sender.send(2);
+        assert_eq!(probe.len(), 2);
+        assert_eq!(probe.try_recv(), Some(1));
+        assert_eq!(probe.drain(), vec![2]);
+        assert_eq!(probe.try_recv(), None);
+    }
+}
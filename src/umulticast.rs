@@ -0,0 +1,158 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+use crate::USender;
+
+/// Identifier of a [`UMulticast`] subscription.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct USubscription(u64);
+
+/// Predicate selecting messages delivered to a subscriber.
+pub type UFilter<Msg> = Arc<dyn Fn(&Msg) -> bool + Send + Sync>;
+
+type UDeliver<Msg> = Arc<dyn Fn(Msg) -> bool + Send + Sync>;
+
+pub(crate) struct Subscriber<Msg> {
+    id: USubscription,
+    deliver: UDeliver<Msg>,
+    filter: Option<UFilter<Msg>>,
+}
+
+impl<Msg> Clone for Subscriber<Msg> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            deliver: self.deliver.clone(),
+            filter: self.filter.clone(),
+        }
+    }
+}
+
+/// Group of services receiving copies of each message sent to the group.
+///
+/// Subscribers whose channels got disconnected are removed from the group on the next send.
+pub struct UMulticast<Msg: Clone> {
+    subscribers: Vec<Subscriber<Msg>>,
+    next_id: u64,
+}
+
+impl<Msg: Clone> Default for UMulticast<Msg> {
+    fn default() -> Self { Self { subscribers: vec![], next_id: 0 } }
+}
+
+impl<Msg: Clone> Debug for UMulticast<Msg> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UMulticast")
+            .field("subscribers", &self.subscribers.iter().map(|s| s.id).collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<Msg: Clone> UMulticast<Msg> {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn subscribe(&mut self, sender: USender<Msg>) -> USubscription
+    where Msg: Send + 'static {
+        self.subscribe_into(sender)
+    }
+
+    /// Subscribes a service which receives the messages converted into its own message type.
+    pub fn subscribe_into<M>(&mut self, sender: USender<M>) -> USubscription
+    where
+        Msg: Into<M>,
+        M: Send + 'static,
+    {
+        self.add(Arc::new(move |msg: Msg| sender.send(msg.into()).is_ok()), None)
+    }
+
+    /// Subscribes the sender to the messages matching the `filter` predicate.
+    pub fn subscribe_filtered(
+        &mut self,
+        sender: USender<Msg>,
+        filter: impl Fn(&Msg) -> bool + Send + Sync + 'static,
+    ) -> USubscription
+    where
+        Msg: Send + 'static,
+    {
+        self.add(Arc::new(move |msg| sender.send(msg).is_ok()), Some(Arc::new(filter)))
+    }
+
+    fn add(&mut self, deliver: UDeliver<Msg>, filter: Option<UFilter<Msg>>) -> USubscription {
+        let id = USubscription(self.next_id);
+        self.next_id += 1;
+        self.subscribers.push(Subscriber { id, deliver, filter });
+        id
+    }
+
+    /// Removes the subscription, returning whether it was present.
+    pub fn unsubscribe(&mut self, id: USubscription) -> bool {
+        let len = self.subscribers.len();
+        self.subscribers.retain(|subscriber| subscriber.id != id);
+        self.subscribers.len() != len
+    }
+
+    pub fn is_subscribed(&self, id: USubscription) -> bool {
+        self.subscribers
+            .iter()
+            .any(|subscriber| subscriber.id == id)
+    }
+
+    pub fn is_empty(&self) -> bool { self.subscribers.is_empty() }
+
+    pub fn len(&self) -> usize { self.subscribers.len() }
+
+    /// Sends a copy of the message to each subscriber whose filter accepts it, removing
+    /// disconnected subscribers. Returns the number of subscribers which have received the
+    /// message.
+    pub fn send(&mut self, msg: Msg) -> usize {
+        let (count, disconnected) = Self::deliver(&self.subscribers, msg);
+        self.subscribers
+            .retain(|subscriber| !disconnected.contains(&subscriber.id));
+        count
+    }
+
+    /// Copy of the subscriber list, for delivering messages without holding a lock on the group.
+    pub(crate) fn subscribers(&self) -> Vec<Subscriber<Msg>> { self.subscribers.clone() }
+
+    /// Sends a copy of the message to each subscriber whose filter accepts it. Returns the number
+    /// of subscribers which have received the message and the disconnected subscriptions.
+    pub(crate) fn deliver(
+        subscribers: &[Subscriber<Msg>],
+        msg: Msg,
+    ) -> (usize, Vec<USubscription>) {
+        let mut count = 0;
+        let mut disconnected = vec![];
+        for subscriber in subscribers {
+            if !subscriber.filter.as_ref().is_none_or(|filter| filter(&msg)) {
+                continue;
+            }
+            if (subscriber.deliver)(msg.clone()) {
+                count += 1;
+            } else {
+                disconnected.push(subscriber.id);
+            }
+        }
+        (count, disconnected)
+    }
+}
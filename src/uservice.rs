@@ -19,8 +19,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::fmt::Display;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
 use std::ops::ControlFlow;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use crossbeam_channel::{SendError, SendTimeoutError, Sender, TrySendError};
@@ -31,7 +33,13 @@ pub type UResult<T = ()> = Result<T, UError>;
 #[derive(Clone, Debug)]
 pub struct UResponder<T = (), E = UError>(Option<Sender<Result<T, E>>>);
 
+impl<T, E> Default for UResponder<T, E> {
+    fn default() -> Self { Self(None) }
+}
+
 impl<T, E> UResponder<T, E> {
+    pub fn new(sender: Sender<Result<T, E>>) -> Self { Self(Some(sender)) }
+
     pub fn respond(&self, msg: Result<T, E>) -> Result<(), SendError<Result<T, E>>> {
         if let Some(sender) = &self.0 { sender.send(msg) } else { Ok(()) }
     }
@@ -43,10 +51,163 @@ pub(crate) enum UMsg<Msg> {
     Terminate,
 }
 
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum UFailureKind {
+    /// Error returned from [`UService::process`].
+    Process,
+    /// Error returned from [`UService::tick`].
+    Tick,
+    /// Channel to the service got disconnected.
+    Disconnected,
+    #[default]
+    Other,
+}
+
+impl Display for UFailureKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UFailureKind::Process => "service process error",
+            UFailureKind::Tick => "service tick error",
+            UFailureKind::Disconnected => "channel to the service is broken",
+            UFailureKind::Other => "service error",
+        })
+    }
+}
+
+/// Structured error of a service, which can be matched on its kind.
 #[derive(Clone, Debug)]
+pub struct UFailure {
+    pub service: String,
+    pub kind: UFailureKind,
+    pub message: String,
+    pub source: Option<Arc<dyn Error + Send + Sync>>,
+}
+
+impl UFailure {
+    pub fn new(kind: UFailureKind, message: impl Display) -> Self {
+        Self {
+            service: String::new(),
+            kind,
+            message: message.to_string(),
+            source: None,
+        }
+    }
+
+    /// Failure of [`UFailureKind::Other`] kind, converted from any displayable error.
+    pub fn other(err: impl Display) -> Self { Self::new(UFailureKind::Other, err) }
+
+    pub fn with_service(mut self, service: impl ToString) -> Self {
+        self.service = service.to_string();
+        self
+    }
+
+    pub fn with_source(mut self, source: impl Error + Send + Sync + 'static) -> Self {
+        self.source = Some(Arc::new(source));
+        self
+    }
+
+    pub fn is_kind(&self, kind: UFailureKind) -> bool { self.kind == kind }
+}
+
+impl Display for UFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if !self.service.is_empty() {
+            write!(f, "{}: ", self.service)?;
+        }
+        write!(f, "{} - {}", self.kind, self.message)
+    }
+}
+
+impl Error for UFailure {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|err| err as &(dyn Error + 'static))
+    }
+}
+
+impl From<UError> for UFailure {
+    fn from(err: UError) -> Self { Self::other(err) }
+}
+
+impl From<UErrorMsg> for UFailure {
+    fn from(msg: UErrorMsg) -> Self { Self::new(msg.kind, msg.error).with_service(msg.service) }
+}
+
+/// Severity of a report sent to the service monitor.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub enum USeverity {
+    Info,
+    Warn,
+    #[default]
+    Error,
+    /// The service can't continue operating.
+    Fatal,
+}
+
+impl Display for USeverity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            USeverity::Info => "Info",
+            USeverity::Warn => "Warning",
+            USeverity::Error => "Error",
+            USeverity::Fatal => "Fatal error",
+        })
+    }
+}
+
+/// Report sent by a service to its monitor.
+///
+/// New fields may be added to the report, so it can be constructed only with
+/// [`UErrorMsg::new`] and the builder methods.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
 pub struct UErrorMsg {
     pub service: String,
+    pub severity: USeverity,
+    pub kind: UFailureKind,
     pub error: String,
+    /// Structured details of the report, as key-value pairs.
+    pub fields: Vec<(String, String)>,
+}
+
+impl UErrorMsg {
+    pub fn new(service: impl ToString, severity: USeverity, error: impl ToString) -> Self {
+        Self {
+            service: service.to_string(),
+            severity,
+            kind: UFailureKind::Other,
+            error: error.to_string(),
+            fields: vec![],
+        }
+    }
+
+    pub fn with_kind(mut self, kind: UFailureKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn with_field(mut self, key: impl ToString, value: impl Display) -> Self {
+        self.fields.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn field(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl Display for UErrorMsg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} in {}: {}", self.severity, self.service, self.error)?;
+        for (key, value) in &self.fields {
+            write!(f, " {key}={value}")?;
+        }
+        Ok(())
+    }
 }
 
 pub trait UService: Send + 'static {
@@ -68,6 +229,12 @@ pub trait UService: Send + 'static {
 
     fn error_brief(&self, err: impl Display) { self.error_sender().report_brief(err.to_string()) }
 
+    /// Reports a failure detected while running the service; by default passes it to
+    /// [`UService::error`] with the failure kind as the context. Services may override it to
+    /// react to particular kinds of failures, or to report the kind to the monitor with
+    /// [`UErrorSender::report_failure`].
+    fn failure(&self, failure: UFailure) { self.error(&failure.kind.to_string(), failure.message) }
+
     fn error_sender(&self) -> UErrorSender {
         UErrorSender { sender: self.monitor().cloned(), service_name: Self::NAME }
     }
@@ -92,29 +259,51 @@ impl UErrorSender {
     }
 
     pub fn report_brief(&self, err: impl ToString) {
+        self.send(UErrorMsg::new(self.service_name, USeverity::Error, err.to_string()))
+    }
+
+    pub fn report_failure(&self, failure: &UFailure) {
+        let error = format!("{} - {}", failure.kind, failure.message);
+        self.send(
+            UErrorMsg::new(self.service_name, USeverity::Error, error).with_kind(failure.kind),
+        )
+    }
+
+    /// Reports a message with the given severity.
+    pub fn notify(&self, severity: USeverity, msg: impl ToString) {
+        self.send(UErrorMsg::new(self.service_name, severity, msg.to_string()))
+    }
+
+    /// Reports a message with structured fields, built with [`UErrorMsg`] methods.
+    pub fn send(&self, msg: UErrorMsg) {
         #[cfg(feature = "log")]
-        {
-            let error = err.to_string();
-            log::error!(target: self.service_name, "{error}");
-
-            let Some(sender) = &self.sender else {
-                return;
-            };
-            if sender
-                .send(UErrorMsg { service: self.service_name.to_string(), error })
-                .is_err()
-            {
-                log::error!(target: self.service_name, "Broken monitor channel");
+        match msg.severity {
+            USeverity::Info => log::info!(target: self.service_name, "{}", msg.error),
+            USeverity::Warn => log::warn!(target: self.service_name, "{}", msg.error),
+            USeverity::Error | USeverity::Fatal => {
+                log::error!(target: self.service_name, "{}", msg.error)
             }
         }
         #[cfg(feature = "stderr")]
-        eprintln!("Error in {}: {}", self.service_name, err.to_string());
+        eprintln!("{msg}");
+
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        if sender.send(msg).is_err() {
+            #[cfg(feature = "log")]
+            log::error!(target: self.service_name, "Broken monitor channel");
+        }
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct USender<Msg>(pub(crate) Sender<UMsg<Msg>>);
 
+impl<Msg> Clone for USender<Msg> {
+    fn clone(&self) -> Self { Self(self.0.clone()) }
+}
+
 impl<Msg> USender<Msg> {
     fn convert_timeout_error(err: SendTimeoutError<UMsg<Msg>>) -> SendTimeoutError<Msg> {
         match err {
@@ -170,3 +359,44 @@ impl<Msg> USender<Msg> {
     #[inline]
     pub fn capacity(&self) -> Option<usize> { self.0.capacity() }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::TestExecutor;
+
+    #[derive(Default)]
+    struct Failing(Mutex<Vec<String>>);
+
+    impl UService for Failing {
+        type Msg = ();
+        type Error = &'static str;
+        const NAME: &'static str = "failing";
+
+        fn tick(&mut self) -> Result<(), Self::Error> { Err("tick failed") }
+
+        fn process(&mut self, _: ()) -> Result<ControlFlow<u8>, Self::Error> {
+            Err("process failed")
+        }
+
+        fn terminate(&mut self) {}
+
+        fn error(&self, context: &str, err: impl Display) {
+            self.0.lock().unwrap().push(format!("{context} - {err}"));
+        }
+    }
+
+    #[test]
+    fn failures_reach_error() {
+        let mut executor = TestExecutor::new(Failing::default(), Some(Duration::from_secs(1)));
+        executor.deliver(());
+        executor.advance(Duration::from_secs(1));
+        assert_eq!(*executor.service().0.lock().unwrap(), vec![
+            "service process error - process failed",
+            "service tick error - tick failed"
+        ]);
+    }
+}
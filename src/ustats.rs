@@ -0,0 +1,93 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default)]
+pub(crate) struct UCounters {
+    processed: AtomicU64,
+    ticks: AtomicU64,
+    errors: AtomicU64,
+    busy_nanos: AtomicU64,
+}
+
+impl UCounters {
+    pub(crate) fn record_msg(&self, started: Instant, failed: bool) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+        self.record(started, failed);
+    }
+
+    pub(crate) fn record_tick(&self, started: Instant, failed: bool) {
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+        self.record(started, failed);
+    }
+
+    fn record(&self, started: Instant, failed: bool) {
+        if failed {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        let nanos = started.elapsed().as_nanos().min(u64::MAX as u128) as u64;
+        self.busy_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> UStats {
+        UStats {
+            processed: self.processed.load(Ordering::Relaxed),
+            ticks: self.ticks.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            busy: Duration::from_nanos(self.busy_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Performance counters of a service event loop.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct UStats {
+    /// Number of messages passed to [`crate::UService::process`].
+    pub processed: u64,
+    /// Number of [`crate::UService::tick`] calls.
+    pub ticks: u64,
+    /// Number of messages and ticks which have returned an error.
+    pub errors: u64,
+    /// Total time spent inside the service message processing and ticks.
+    pub busy: Duration,
+}
+
+impl UStats {
+    /// Average time spent by the service per processed message or tick.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        let count = self.processed + self.ticks;
+        if count == 0 {
+            return None;
+        }
+        Some(self.busy / count.min(u32::MAX as u64) as u32)
+    }
+
+    /// Number of messages the service is able to process per second of its busy time.
+    pub fn throughput(&self) -> Option<f64> {
+        let busy = self.busy.as_secs_f64();
+        if busy == 0.0 {
+            return None;
+        }
+        Some(self.processed as f64 / busy)
+    }
+}
@@ -19,36 +19,226 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fmt::{self, Display, Formatter};
 use std::ops::ControlFlow;
-use std::thread;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread::JoinHandle;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use std::{io, thread};
 
-use crossbeam_channel::{RecvTimeoutError, Sender};
+use crossbeam_channel::{
+    Receiver, RecvTimeoutError, Select, SendTimeoutError, Sender, TryRecvError,
+};
 
+use crate::uclock::{Clock, SystemClock};
 use crate::uservice::UMsg;
-use crate::{USender, UService};
+#[cfg(feature = "bench")]
+use crate::ustats::{UCounters, UStats};
+use crate::{UFailure, UFailureKind, UHeartbeat, USender, UService};
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct ThreadConfig {
+    /// Thread name; defaults to the service name.
+    pub name: Option<String>,
+    /// Stack size in bytes; defaults to the platform default for spawned threads.
+    pub stack_size: Option<usize>,
+    /// Maximum number of messages queued to the service, after which senders block; unbounded
+    /// by default.
+    pub capacity: Option<usize>,
+    /// Niceness of the thread, from -20 (highest priority) to 19 (lowest priority); lowering the
+    /// niceness below the default usually requires privileges. Supported on Linux only.
+    pub nice: Option<i32>,
+    /// CPUs the thread is allowed to run on. Supported on Linux only.
+    pub affinity: Option<Vec<usize>>,
+}
+
+impl ThreadConfig {
+    pub fn named(name: impl ToString) -> Self {
+        Self { name: Some(name.to_string()), ..Self::default() }
+    }
+
+    pub fn with_stack_size(mut self, stack_size: usize) -> Self {
+        self.stack_size = Some(stack_size);
+        self
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    pub fn with_nice(mut self, nice: i32) -> Self {
+        self.nice = Some(nice);
+        self
+    }
+
+    pub fn with_affinity(mut self, cpus: impl IntoIterator<Item = usize>) -> Self {
+        self.affinity = Some(cpus.into_iter().collect());
+        self
+    }
+
+    /// Applies the niceness and affinity settings to the current thread.
+    fn apply(&self) -> io::Result<()> {
+        if let Some(nice) = self.nice {
+            os::set_nice(nice)?;
+        }
+        if let Some(cpus) = &self.affinity {
+            os::set_affinity(cpus)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod os {
+    use std::{io, mem};
+
+    pub fn set_nice(nice: i32) -> io::Result<()> {
+        // On Linux the niceness is a per-thread attribute, addressed by the thread id.
+        let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, nice) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn set_affinity(cpus: &[usize]) -> io::Result<()> {
+        let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+        for &cpu in cpus {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "CPU index is out of range",
+                ));
+            }
+            unsafe { libc::CPU_SET(cpu, &mut set) };
+        }
+        // Zero pid stands for the calling thread.
+        if unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod os {
+    use std::io;
+
+    pub fn set_nice(_: i32) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "thread niceness is supported on Linux only",
+        ))
+    }
+
+    pub fn set_affinity(_: &[usize]) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "thread affinity is supported on Linux only",
+        ))
+    }
+}
 
 #[derive(Debug)]
 pub struct UThread<S: UService> {
     thread: Option<JoinHandle<()>>,
     sender: Sender<UMsg<S::Msg>>,
+    paused: Arc<AtomicBool>,
+    stopped: Receiver<()>,
+    terminating: bool,
+    heartbeat: UHeartbeat,
+    #[cfg(feature = "bench")]
+    counters: Arc<UCounters>,
 }
 
 impl<S: UService> UThread<S> {
-    pub fn new(mut service: S, ticks: Option<Duration>) -> Self {
-        let (sender, receiver) = crossbeam_channel::unbounded();
+    pub fn new(service: S, ticks: Option<Duration>) -> Self {
+        Self::with_config(service, ticks, ThreadConfig::default())
+            .unwrap_or_else(|err| panic!("unable to spawn the {} thread: {err}", S::NAME))
+    }
+
+    /// Spawns the service thread; fails if the thread can't be spawned or its niceness or
+    /// affinity can't be set.
+    pub fn with_config(
+        service: S,
+        ticks: Option<Duration>,
+        config: ThreadConfig,
+    ) -> io::Result<Self> {
+        Self::with_clock(service, ticks, config, SystemClock)
+    }
+
+    /// Spawns the service thread measuring tick intervals with the provided `clock`.
+    pub fn with_clock(
+        mut service: S,
+        ticks: Option<Duration>,
+        config: ThreadConfig,
+        clock: impl Clock,
+    ) -> io::Result<Self> {
+        let (sender, receiver) = match config.capacity {
+            Some(capacity) => crossbeam_channel::bounded(capacity),
+            None => crossbeam_channel::unbounded(),
+        };
         service.set_self_sender(USender(sender.clone()));
-        let thread = thread::spawn(move || {
+        let name = config.name.clone().unwrap_or(S::NAME.to_owned());
+        let mut builder = thread::Builder::new().name(name.clone());
+        if let Some(stack_size) = config.stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+        let paused = Arc::new(AtomicBool::new(false));
+        let thread_paused = paused.clone();
+        // Dropped by the thread on exit, which disconnects the `stopped` receiver.
+        let (stop_guard, stopped) = crossbeam_channel::bounded::<()>(0);
+        let heartbeat = UHeartbeat::new(S::NAME, name, clock.now());
+        let thread_heartbeat = heartbeat.clone();
+        #[cfg(feature = "bench")]
+        let counters = Arc::new(UCounters::default());
+        #[cfg(feature = "bench")]
+        let thread_counters = counters.clone();
+        let (started, start_result) = crossbeam_channel::bounded(1);
+        let thread = builder.spawn(move || {
+            let _stop_guard = stop_guard;
+            let res = config.apply();
+            let failed = res.is_err();
+            let _ = started.send(res);
+            if failed {
+                return;
+            }
+            let mut timer: Option<(Instant, Receiver<Instant>)> = None;
             loop {
-                let recv = || {
-                    if let Some(timeout) = ticks {
-                        receiver.recv_timeout(timeout)
-                    } else {
-                        receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+                while thread_paused.load(Ordering::Acquire) {
+                    thread::park();
+                }
+                let mut select = Select::new();
+                select.recv(&receiver);
+                if let Some(ticks) = ticks {
+                    // While the clock does not move, the same timer can be reused.
+                    let deadline = clock.now() + ticks;
+                    if timer.as_ref().is_none_or(|(at, _)| *at != deadline) {
+                        timer = Some((deadline, clock.at(deadline)));
+                    }
+                    let (_, tick_timer) = timer.as_ref().expect("timer is set above");
+                    select.recv(tick_timer);
+                }
+                let ready = select.ready();
+                drop(select);
+                // The service may have been paused while waiting; the message is left in the
+                // mailbox and the tick is skipped then.
+                if thread_paused.load(Ordering::Acquire) {
+                    continue;
+                }
+                let recv = if ready == 0 {
+                    match receiver.try_recv() {
+                        Ok(msg) => Ok(msg),
+                        Err(TryRecvError::Empty) => continue,
+                        Err(TryRecvError::Disconnected) => Err(RecvTimeoutError::Disconnected),
                     }
+                } else {
+                    timer = None;
+                    Err(RecvTimeoutError::Timeout)
                 };
-                let msg = match recv() {
+                let msg = match recv {
                     Ok(UMsg::Msg(msg)) => msg,
                     Ok(UMsg::Terminate) => {
                         #[cfg(feature = "log")]
@@ -59,21 +249,38 @@ impl<S: UService> UThread<S> {
                     Err(RecvTimeoutError::Timeout) => {
                         #[cfg(feature = "log")]
                         log::trace!(target: S::NAME, "timed out, restarting the event loop");
-                        if let Err(err) = service.tick() {
-                            service.error("service tick error", err)
+                        #[cfg(feature = "bench")]
+                        let started = Instant::now();
+                        thread_heartbeat.busy(clock.now());
+                        let res = service.tick();
+                        thread_heartbeat.idle();
+                        #[cfg(feature = "bench")]
+                        thread_counters.record_tick(started, res.is_err());
+                        if let Err(err) = res {
+                            let failure = UFailure::new(UFailureKind::Tick, err);
+                            service.failure(failure.with_service(S::NAME))
                         };
                         continue;
                     }
                     Err(RecvTimeoutError::Disconnected) => {
                         #[cfg(feature = "log")]
                         log::error!(target: S::NAME, "service channel got disconnected");
-                        service.error("channel to the service is broken", "disconnected");
+                        let failure = UFailure::new(UFailureKind::Disconnected, "disconnected");
+                        service.failure(failure.with_service(S::NAME));
                         break;
                     }
                 };
-                match service.process(msg) {
+                #[cfg(feature = "bench")]
+                let started = Instant::now();
+                thread_heartbeat.busy(clock.now());
+                let res = service.process(msg);
+                thread_heartbeat.idle();
+                #[cfg(feature = "bench")]
+                thread_counters.record_msg(started, res.is_err());
+                match res {
                     Err(err) => {
-                        service.error("service process error", err);
+                        let failure = UFailure::new(UFailureKind::Process, err);
+                        service.failure(failure.with_service(S::NAME));
                     }
                     Ok(ControlFlow::Break(code)) => {
                         if code == 0 {
@@ -81,7 +288,10 @@ impl<S: UService> UThread<S> {
                             log::info!(target: S::NAME, "thread is stopping on service request");
                         } else {
                             #[cfg(feature = "log")]
-                            log::debug!(target: S::NAME, "stopping thread due to status {code} returned from the service");
+                            log::debug!(
+                                target: S::NAME,
+                                "stopping thread due to status {code} returned from the service"
+                            );
                         }
                         service.terminate();
                         break;
@@ -91,13 +301,85 @@ impl<S: UService> UThread<S> {
             }
             #[cfg(feature = "log")]
             log::info!(target: S::NAME, "thread is stopped");
-        });
+        })?;
+        if let Ok(Err(err)) = start_result.recv() {
+            let _ = thread.join();
+            return Err(err);
+        }
 
-        Self { thread: Some(thread), sender }
+        Ok(Self {
+            thread: Some(thread),
+            sender,
+            paused,
+            stopped,
+            terminating: false,
+            heartbeat,
+            #[cfg(feature = "bench")]
+            counters,
+        })
     }
 
     pub fn sender(&self) -> USender<S::Msg> { USender(self.sender.clone()) }
 
+    /// Number of messages queued to the service and not yet taken for processing.
+    pub fn pending(&self) -> usize { self.sender.len() }
+
+    pub fn is_paused(&self) -> bool { self.paused.load(Ordering::Acquire) }
+
+    /// Stops the service from taking new messages from its mailbox and from ticking; messages
+    /// sent to the service keep being queued. The message currently being processed, if any, is
+    /// completed.
+    pub fn pause(&self) { self.paused.store(true, Ordering::Release); }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Release);
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+    }
+
+    /// Orders the service to terminate after it processes all messages queued so far, and waits
+    /// for the service thread to complete.
+    ///
+    /// Messages sent after this call are rejected once the thread stops. If the thread has not
+    /// completed within the `timeout`, returns the number of messages still pending; the thread
+    /// continues draining the queue and is joined on drop.
+    pub fn drain(&mut self, timeout: Duration) -> Result<(), DrainError> {
+        let Some(thread) = &self.thread else {
+            return Ok(());
+        };
+        let deadline = Instant::now() + timeout;
+        // The paused thread must be running to free space in a bounded mailbox for the command.
+        self.resume();
+        if !self.terminating {
+            #[cfg(feature = "log")]
+            log::debug!(
+                target: S::NAME,
+                "ordering service to terminate after draining {} message(s)",
+                self.pending()
+            );
+            match self.sender.send_deadline(UMsg::Terminate, deadline) {
+                // A disconnected channel means the service has already stopped by itself.
+                Ok(()) | Err(SendTimeoutError::Disconnected(_)) => self.terminating = true,
+                Err(SendTimeoutError::Timeout(_)) => {
+                    return Err(DrainError::Timeout(self.pending()));
+                }
+            }
+        }
+        if !thread.is_finished()
+            && self.stopped.recv_deadline(deadline) != Err(RecvTimeoutError::Disconnected)
+        {
+            return Err(DrainError::Timeout(self.pending()));
+        }
+        self.join().map_err(|_| DrainError::Panicked)
+    }
+
+    /// Liveness record of the service thread, to be watched by a [`crate::Watchdog`].
+    pub fn heartbeat(&self) -> UHeartbeat { self.heartbeat.clone() }
+
+    #[cfg(feature = "bench")]
+    pub fn stats(&self) -> UStats { self.counters.snapshot() }
+
     pub fn join(&mut self) -> thread::Result<()> {
         if let Some(thread) = self.thread.take() {
             return thread.join().inspect_err(|_| {
@@ -109,13 +391,42 @@ impl<S: UService> UThread<S> {
     }
 }
 
+/// Error stopping a service thread with [`UThread::drain`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum DrainError {
+    /// The thread has not completed within the timeout, having the given number of messages
+    /// still pending.
+    Timeout(usize),
+    /// The service thread has panicked.
+    Panicked,
+}
+
+impl Display for DrainError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DrainError::Timeout(pending) => {
+                write!(f, "service has not completed in time, having {pending} message(s) pending")
+            }
+            DrainError::Panicked => f.write_str("service thread has panicked"),
+        }
+    }
+}
+
+impl std::error::Error for DrainError {}
+
 impl<S: UService> Drop for UThread<S> {
     fn drop(&mut self) {
-        #[cfg(feature = "log")]
-        log::debug!(target: S::NAME, "ordering service to terminate");
-        self.sender.send(UMsg::Terminate).unwrap_or_else(|err| {
-            panic!("unable to send terminate command to the {} thread: {err}", S::NAME)
-        });
+        if self.thread.is_none() {
+            return;
+        }
+        // The paused thread must be running to free space in a bounded mailbox for the command.
+        self.resume();
+        if !self.terminating {
+            #[cfg(feature = "log")]
+            log::debug!(target: S::NAME, "ordering service to terminate");
+            // A disconnected channel means the service has already stopped by itself.
+            let _ = self.sender.send(UMsg::Terminate);
+        }
         if let Some(thread) = self.thread.take() {
             #[cfg(feature = "log")]
             log::info!(target: S::NAME, "waiting for the service thread to complete");
@@ -125,3 +436,246 @@ impl<S: UService> Drop for UThread<S> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::{MockClock, UProbe};
+
+    /// Forwards each message to the probe, stopping on zero and panicking on `u32::MAX`.
+    struct Echo {
+        out: USender<u32>,
+        delay: Duration,
+        ticks: Arc<AtomicUsize>,
+    }
+
+    impl Echo {
+        fn new(probe: &UProbe<u32>) -> Self {
+            Self {
+                out: probe.sender(),
+                delay: Duration::ZERO,
+                ticks: Arc::default(),
+            }
+        }
+    }
+
+    impl UService for Echo {
+        type Msg = u32;
+        type Error = String;
+        const NAME: &'static str = "echo";
+
+        fn tick(&mut self) -> Result<(), Self::Error> {
+            self.ticks.fetch_add(1, Ordering::AcqRel);
+            Ok(())
+        }
+
+        fn process(&mut self, msg: u32) -> Result<ControlFlow<u8>, Self::Error> {
+            thread::sleep(self.delay);
+            assert_ne!(msg, u32::MAX, "test panic");
+            let _ = self.out.send(msg);
+            Ok(if msg == 0 { ControlFlow::Break(0) } else { ControlFlow::Continue(()) })
+        }
+
+        fn terminate(&mut self) {}
+    }
+
+    fn wait_for(probe: &UProbe<u32>, timeout: Duration) -> Option<u32> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if let Some(msg) = probe.try_recv() {
+                return Some(msg);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        None
+    }
+
+    #[test]
+    fn pause_stops_intake() {
+        let probe = UProbe::new();
+        let thread = UThread::new(Echo::new(&probe), None);
+        thread.pause();
+        thread.sender().send(1).unwrap_or_else(|_| panic!());
+        assert_eq!(wait_for(&probe, Duration::from_millis(50)), None);
+        assert_eq!(thread.pending(), 1);
+        thread.resume();
+        assert_eq!(wait_for(&probe, Duration::from_secs(1)), Some(1));
+        assert_eq!(thread.pending(), 0);
+    }
+
+    #[test]
+    fn pause_stops_ticks() {
+        let probe = UProbe::new();
+        let echo = Echo::new(&probe);
+        let ticks = echo.ticks.clone();
+        let thread = UThread::new(echo, Some(Duration::from_millis(2)));
+        thread::sleep(Duration::from_millis(20));
+        thread.pause();
+        thread::sleep(Duration::from_millis(10));
+        let paused_ticks = ticks.load(Ordering::Acquire);
+        assert!(paused_ticks > 0);
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(ticks.load(Ordering::Acquire), paused_ticks);
+        thread.resume();
+        thread::sleep(Duration::from_millis(30));
+        assert!(ticks.load(Ordering::Acquire) > paused_ticks);
+    }
+
+    #[test]
+    fn ticks_with_mock_clock() {
+        let probe = UProbe::new();
+        let echo = Echo::new(&probe);
+        let ticks = echo.ticks.clone();
+        let clock = MockClock::new();
+        let _thread = UThread::with_clock(
+            echo,
+            Some(Duration::from_secs(10)),
+            ThreadConfig::default(),
+            clock.clone(),
+        )
+        .unwrap_or_else(|err| panic!("{err}"));
+        for expected in 1..=3 {
+            while clock.pending_timers() == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            clock.advance(Duration::from_secs(9));
+            thread::sleep(Duration::from_millis(20));
+            assert_eq!(ticks.load(Ordering::Acquire), expected - 1);
+            clock.advance(Duration::from_secs(1));
+            while ticks.load(Ordering::Acquire) < expected {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    #[test]
+    fn drain_paused_full_mailbox() {
+        let probe = UProbe::new();
+        let config = ThreadConfig::default().with_capacity(1);
+        let mut thread = UThread::with_config(Echo::new(&probe), None, config)
+            .unwrap_or_else(|err| panic!("{err}"));
+        thread.pause();
+        thread.sender().send(1).unwrap_or_else(|_| panic!());
+        assert!(thread.sender().is_full());
+        assert_eq!(thread.drain(Duration::from_secs(1)), Ok(()));
+        assert_eq!(probe.drain(), vec![1]);
+        assert!(thread.sender().send(2).is_err());
+    }
+
+    #[test]
+    fn drain_honours_timeout() {
+        let probe = UProbe::new();
+        let echo = Echo { delay: Duration::from_millis(200), ..Echo::new(&probe) };
+        let config = ThreadConfig::default().with_capacity(1);
+        let mut thread =
+            UThread::with_config(echo, None, config).unwrap_or_else(|err| panic!("{err}"));
+        thread.sender().send(1).unwrap_or_else(|_| panic!());
+        thread.sender().send(2).unwrap_or_else(|_| panic!());
+        let started = Instant::now();
+        assert!(matches!(thread.drain(Duration::from_millis(20)), Err(DrainError::Timeout(_))));
+        assert!(started.elapsed() < Duration::from_millis(150));
+        assert_eq!(thread.drain(Duration::from_secs(2)), Ok(()));
+        assert_eq!(probe.drain(), vec![1, 2]);
+    }
+
+    #[test]
+    fn drop_self_stopped() {
+        let probe = UProbe::new();
+        let thread = UThread::new(Echo::new(&probe), None);
+        thread.sender().send(0).unwrap_or_else(|_| panic!());
+        assert_eq!(wait_for(&probe, Duration::from_secs(1)), Some(0));
+        thread::sleep(Duration::from_millis(10));
+        drop(thread);
+
+        let mut thread = UThread::new(Echo::new(&probe), None);
+        thread.sender().send(0).unwrap_or_else(|_| panic!());
+        assert_eq!(wait_for(&probe, Duration::from_secs(1)), Some(0));
+        assert_eq!(thread.drain(Duration::from_secs(1)), Ok(()));
+    }
+
+    #[test]
+    fn drain_panicked() {
+        let probe = UProbe::new();
+        let mut thread = UThread::new(Echo::new(&probe), None);
+        thread.sender().send(u32::MAX).unwrap_or_else(|_| panic!());
+        assert_eq!(thread.drain(Duration::from_secs(1)), Err(DrainError::Panicked));
+        assert!(probe.is_empty());
+        drop(thread);
+    }
+
+    #[test]
+    fn drop_paused() {
+        let probe = UProbe::new();
+        let config = ThreadConfig::default().with_capacity(1);
+        let thread = UThread::with_config(Echo::new(&probe), None, config)
+            .unwrap_or_else(|err| panic!("{err}"));
+        thread.pause();
+        thread.sender().send(1).unwrap_or_else(|_| panic!());
+        drop(thread);
+        assert_eq!(probe.drain(), vec![1]);
+    }
+
+    /// Niceness of the calling thread.
+    #[cfg(target_os = "linux")]
+    fn current_nice() -> i32 {
+        let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+        unsafe { libc::getpriority(libc::PRIO_PROCESS, tid) }
+    }
+
+    /// CPUs the calling thread is allowed to run on.
+    #[cfg(target_os = "linux")]
+    fn current_cpus() -> Vec<usize> {
+        let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        unsafe { libc::sched_getaffinity(0, size_of::<libc::cpu_set_t>(), &mut set) };
+        (0..libc::CPU_SETSIZE as usize)
+            .filter(|cpu| unsafe { libc::CPU_ISSET(*cpu, &set) })
+            .collect()
+    }
+
+    #[cfg(target_os = "linux")]
+    #[derive(Debug)]
+    struct Sched;
+
+    #[cfg(target_os = "linux")]
+    impl UService for Sched {
+        /// Receives the niceness and the CPUs allowed for the thread.
+        type Msg = USender<(i32, Vec<usize>)>;
+        type Error = String;
+        const NAME: &'static str = "sched";
+
+        fn process(&mut self, reply: Self::Msg) -> Result<ControlFlow<u8>, Self::Error> {
+            let _ = reply.send((current_nice(), current_cpus()));
+            Ok(ControlFlow::Continue(()))
+        }
+
+        fn terminate(&mut self) {}
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn nice_and_affinity() {
+        // Raising the niceness and narrowing the allowed CPUs don't require privileges.
+        let nice = (current_nice() + 1).min(19);
+        let cpu = *current_cpus().last().expect("thread runs on some CPU");
+        let config = ThreadConfig::default().with_nice(nice).with_affinity([cpu]);
+        let thread =
+            UThread::with_config(Sched, None, config).unwrap_or_else(|err| panic!("{err}"));
+        let probe = UProbe::new();
+        thread
+            .sender()
+            .send(probe.sender())
+            .unwrap_or_else(|_| panic!());
+        drop(thread);
+        assert_eq!(probe.drain(), vec![(nice, vec![cpu])]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn invalid_affinity() {
+        let config = ThreadConfig::default().with_affinity([usize::MAX]);
+        let err = UThread::with_config(Sched, None, config).expect_err("CPU index is invalid");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
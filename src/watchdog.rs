@@ -0,0 +1,295 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::convert::Infallible;
+use std::fmt::{self, Debug, Formatter};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::{Clock, SystemClock, UErrorMsg, USender, UService, USeverity};
+
+#[derive(Debug)]
+struct Beats {
+    service: &'static str,
+    name: String,
+    base: Instant,
+    /// Nanoseconds since `base` plus one when the service has started its current work, or zero
+    /// while it waits for messages.
+    busy_since: AtomicU64,
+    progress: AtomicU64,
+}
+
+/// Liveness record of a service thread, updated by [`crate::UThread`] around each processed
+/// message and tick.
+///
+/// Heartbeats are equal only if they belong to the same thread.
+#[derive(Clone, Debug)]
+pub struct UHeartbeat(Arc<Beats>);
+
+impl PartialEq for UHeartbeat {
+    fn eq(&self, other: &Self) -> bool { Arc::ptr_eq(&self.0, &other.0) }
+}
+
+impl Eq for UHeartbeat {}
+
+impl UHeartbeat {
+    pub(crate) fn new(service: &'static str, name: String, base: Instant) -> Self {
+        Self(Arc::new(Beats {
+            service,
+            name,
+            base,
+            busy_since: AtomicU64::new(0),
+            progress: AtomicU64::new(0),
+        }))
+    }
+
+    pub(crate) fn busy(&self, now: Instant) {
+        let nanos = now.saturating_duration_since(self.0.base).as_nanos() as u64;
+        self.0.busy_since.store(nanos + 1, Ordering::Release);
+    }
+
+    pub(crate) fn idle(&self) {
+        self.0.busy_since.store(0, Ordering::Release);
+        self.0.progress.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub fn service(&self) -> &'static str { self.0.service }
+
+    /// Name of the service thread, distinguishing threads running services of the same type.
+    pub fn name(&self) -> &str { &self.0.name }
+
+    /// Time when the service has started processing its current message or tick, if any.
+    pub fn busy_since(&self) -> Option<Instant> {
+        match self.0.busy_since.load(Ordering::Acquire) {
+            0 => None,
+            nanos => Some(self.0.base + Duration::from_nanos(nanos - 1)),
+        }
+    }
+
+    /// Number of messages and ticks processed by the service so far.
+    pub fn progress(&self) -> u64 { self.0.progress.load(Ordering::Acquire) }
+}
+
+#[derive(Clone, Debug)]
+pub enum WatchdogMsg {
+    /// Starts watching the service, which must complete each message and tick within `deadline`.
+    Watch {
+        heartbeat: UHeartbeat,
+        deadline: Duration,
+    },
+    Unwatch(UHeartbeat),
+}
+
+#[derive(Debug)]
+struct Watched {
+    heartbeat: UHeartbeat,
+    deadline: Duration,
+    /// Progress of the service at the moment it was reported stuck.
+    stuck_at: Option<u64>,
+}
+
+/// Service detecting other services stuck processing a message or a tick longer than their
+/// deadlines.
+///
+/// Stuck services, and their later recovery, are reported to the monitor. The watchdog checks the
+/// services on each tick, so it must be spawned with ticks, which define the detection
+/// precision.
+pub struct Watchdog {
+    clock: Box<dyn Clock>,
+    watched: Vec<Watched>,
+    monitor: Option<USender<UErrorMsg>>,
+}
+
+impl Debug for Watchdog {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("watched", &self.watched)
+            .finish()
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self { Self::new() }
+}
+
+impl Watchdog {
+    pub fn new() -> Self { Self::with_clock(SystemClock) }
+
+    /// Creates watchdog measuring time with the `clock`, which must be the same clock the watched
+    /// threads use.
+    pub fn with_clock(clock: impl Clock) -> Self {
+        Self { clock: Box::new(clock), watched: vec![], monitor: None }
+    }
+
+    pub fn with_monitor(mut self, monitor: USender<UErrorMsg>) -> Self {
+        self.monitor = Some(monitor);
+        self
+    }
+
+    /// Starts watching the service thread, or updates its deadline if it is already watched.
+    pub fn watch(&mut self, heartbeat: UHeartbeat, deadline: Duration) {
+        match self
+            .watched
+            .iter_mut()
+            .find(|watched| watched.heartbeat == heartbeat)
+        {
+            Some(watched) => watched.deadline = deadline,
+            None => self
+                .watched
+                .push(Watched { heartbeat, deadline, stuck_at: None }),
+        }
+    }
+
+    pub fn unwatch(&mut self, heartbeat: &UHeartbeat) -> bool {
+        let len = self.watched.len();
+        self.watched
+            .retain(|watched| watched.heartbeat != *heartbeat);
+        self.watched.len() != len
+    }
+
+    /// Thread names of the services which are currently stuck.
+    pub fn stuck(&self) -> impl Iterator<Item = &str> + '_ {
+        self.watched
+            .iter()
+            .filter(|watched| watched.stuck_at.is_some())
+            .map(|watched| watched.heartbeat.name())
+    }
+
+    /// Checks the watched services, reporting the ones which got stuck or have recovered.
+    pub fn check(&mut self) {
+        let now = self.clock.now();
+        let mut reports = vec![];
+        for watched in &mut self.watched {
+            let heartbeat = &watched.heartbeat;
+            let progress = heartbeat.progress();
+            match watched.stuck_at {
+                Some(stuck_at) if stuck_at != progress => {
+                    watched.stuck_at = None;
+                    reports.push(UErrorMsg::new(
+                        heartbeat.name(),
+                        USeverity::Info,
+                        "service has resumed making progress",
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    let Some(busy_since) = heartbeat.busy_since() else {
+                        continue;
+                    };
+                    let busy = now.saturating_duration_since(busy_since);
+                    if busy > watched.deadline {
+                        watched.stuck_at = Some(progress);
+                        let msg = format!("service is stuck for {busy:?}");
+                        reports.push(
+                            UErrorMsg::new(heartbeat.name(), USeverity::Error, msg)
+                                .with_field("deadline", format_args!("{:?}", watched.deadline)),
+                        );
+                    }
+                }
+            }
+        }
+        let error_sender = self.error_sender();
+        for report in reports {
+            error_sender.send(report);
+        }
+    }
+}
+
+impl UService for Watchdog {
+    type Msg = WatchdogMsg;
+    type Error = Infallible;
+    const NAME: &'static str = "watchdog";
+
+    fn tick(&mut self) -> Result<(), Self::Error> {
+        self.check();
+        Ok(())
+    }
+
+    fn process(&mut self, msg: Self::Msg) -> Result<ControlFlow<u8>, Self::Error> {
+        match msg {
+            WatchdogMsg::Watch { heartbeat, deadline } => self.watch(heartbeat, deadline),
+            WatchdogMsg::Unwatch(heartbeat) => {
+                self.unwatch(&heartbeat);
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn terminate(&mut self) {}
+
+    fn monitor(&self) -> Option<&USender<UErrorMsg>> { self.monitor.as_ref() }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{MockClock, UProbe};
+
+    #[test]
+    fn same_service_threads() {
+        let clock = MockClock::new();
+        let probe = UProbe::new();
+        let mut watchdog = Watchdog::with_clock(clock.clone()).with_monitor(probe.sender());
+        let first = UHeartbeat::new("worker", "worker-1".to_owned(), clock.now());
+        let second = UHeartbeat::new("worker", "worker-2".to_owned(), clock.now());
+        watchdog.watch(first.clone(), Duration::from_secs(1));
+        watchdog.watch(second.clone(), Duration::from_secs(5));
+        watchdog.watch(first.clone(), Duration::from_secs(2));
+
+        first.busy(clock.now());
+        second.busy(clock.now());
+        clock.advance(Duration::from_secs(3));
+        watchdog.check();
+        assert_eq!(watchdog.stuck().collect::<Vec<_>>(), vec!["worker-1"]);
+        let reports = probe.drain();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].service, "worker-1");
+        assert_eq!(reports[0].severity, USeverity::Error);
+        assert_eq!(reports[0].field("deadline"), Some("2s"));
+
+        first.idle();
+        watchdog.check();
+        assert_eq!(watchdog.stuck().count(), 0);
+        assert_eq!(probe.drain()[0].severity, USeverity::Info);
+
+        assert!(watchdog.unwatch(&first));
+        assert!(!watchdog.unwatch(&first));
+        clock.advance(Duration::from_secs(3));
+        watchdog.check();
+        assert_eq!(watchdog.stuck().collect::<Vec<_>>(), vec!["worker-2"]);
+    }
+
+    #[test]
+    fn idle_is_not_stuck() {
+        let clock = MockClock::new();
+        let mut watchdog = Watchdog::with_clock(clock.clone());
+        let heartbeat = UHeartbeat::new("worker", "worker".to_owned(), clock.now());
+        watchdog.watch(heartbeat.clone(), Duration::from_secs(1));
+        heartbeat.busy(clock.now());
+        heartbeat.idle();
+        clock.advance(Duration::from_secs(10));
+        watchdog.check();
+        assert_eq!(watchdog.stuck().count(), 0);
+        assert_eq!(heartbeat.progress(), 1);
+    }
+}
@@ -0,0 +1,48 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Plugin library loaded by the plugin host tests.
+
+use std::ops::ControlFlow;
+
+use microservices::{PluginRegistrar, RegistryError, USender, UService, UThread, declare_plugin};
+
+/// Doubles the received number and sends it back.
+struct Doubler;
+
+impl UService for Doubler {
+    type Msg = (u32, USender<u32>);
+    type Error = String;
+    const NAME: &'static str = "doubler";
+
+    fn process(&mut self, (n, reply): Self::Msg) -> Result<ControlFlow<u8>, Self::Error> {
+        reply.send(n * 2).map_err(|err| err.to_string())?;
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn terminate(&mut self) {}
+}
+
+fn register(registrar: &mut PluginRegistrar) -> Result<(), RegistryError> {
+    registrar.register("doubler", UThread::new(Doubler, None))
+}
+
+declare_plugin!("test-plugin", register);
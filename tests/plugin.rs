@@ -0,0 +1,131 @@
+// Channel-based non-blocking microservices without use of async
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2022-2025 by
+//     Dr. Maxim Orlovsky <orlovsky@cyphernet.org>
+//
+// Copyright (C) 2022-2025 Cyphernet Labs, InDCS, Switzerland. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use microservices::{PluginError, PluginHost, RegistryError, UProbe, USender};
+
+/// Names and versions of the packages in the lockfile.
+fn locked_packages(lockfile: &Path) -> BTreeSet<(String, String)> {
+    let lock = fs::read_to_string(lockfile).expect("unable to read the lockfile");
+    let value = |line: &str, key: &str| {
+        line.strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix(" = \""))
+            .and_then(|rest| rest.strip_suffix('"'))
+            .map(str::to_owned)
+    };
+    let mut packages = BTreeSet::new();
+    let mut lines = lock.lines();
+    while let Some(line) = lines.next() {
+        if let Some(name) = value(line, "name") {
+            let version = lines.next().and_then(|line| value(line, "version"));
+            packages.insert((name, version.expect("package without version")));
+        }
+    }
+    packages
+}
+
+/// Builds the plugin fixture with the same compiler, this version of the crate and the same
+/// versions of its dependencies, pinned by the host lockfile.
+fn build_fixture() -> &'static Path {
+    static FIXTURE: OnceLock<PathBuf> = OnceLock::new();
+    FIXTURE.get_or_init(|| {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let fixture = root.join("tests/fixtures/plugin");
+        let target_dir = root.join("target").join("plugin-fixture");
+        fs::copy(root.join("Cargo.lock"), fixture.join("Cargo.lock"))
+            .expect("unable to copy the host lockfile");
+        let status = Command::new(env!("CARGO"))
+            .args(["build", "--quiet", "--offline", "--manifest-path"])
+            .arg(fixture.join("Cargo.toml"))
+            .arg("--target-dir")
+            .arg(&target_dir)
+            .status()
+            .expect("unable to run cargo");
+        assert!(status.success(), "unable to build the plugin fixture");
+
+        let host = locked_packages(&root.join("Cargo.lock"));
+        let plugin = locked_packages(&fixture.join("Cargo.lock"));
+        let host_names = host.iter().map(|(name, _)| name).collect::<BTreeSet<_>>();
+        for package in &plugin {
+            if host_names.contains(&package.0) {
+                assert!(host.contains(package), "plugin uses different version of {}", package.0);
+            }
+        }
+
+        let file = format!(
+            "{}microservices_test_plugin{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_SUFFIX
+        );
+        target_dir.join("debug").join(file)
+    })
+}
+
+#[test]
+fn load_call_unload() {
+    let path = build_fixture();
+    let mut host = PluginHost::new();
+
+    let name = unsafe { host.load(path) }.unwrap_or_else(|err| panic!("{err}"));
+    assert_eq!(name, "test-plugin");
+    assert_eq!(host.plugin_services("test-plugin").map(Iterator::collect), Some(vec!["doubler"]));
+    assert!(matches!(unsafe { host.load(path) }, Err(PluginError::Duplicate(_))));
+
+    {
+        let doubler = unsafe { host.sender::<(u32, USender<u32>)>("doubler") }
+            .unwrap_or_else(|err| panic!("{err}"));
+        let probe = UProbe::new();
+        doubler
+            .send((21, probe.sender()))
+            .unwrap_or_else(|_| panic!("plugin service is not running"));
+        assert!(host.unload("test-plugin", Duration::from_secs(1)));
+        assert_eq!(probe.drain(), vec![42]);
+    }
+    assert!(matches!(unsafe { host.sender::<u32>("doubler") }, Err(RegistryError::NotFound(_))));
+    assert!(!host.unload("test-plugin", Duration::from_secs(1)));
+    assert_eq!(host.plugins().count(), 0);
+}
+
+#[test]
+fn type_mismatch() {
+    let path = build_fixture();
+    let mut host = PluginHost::new();
+    unsafe { host.load(path) }.unwrap_or_else(|err| panic!("{err}"));
+    assert!(matches!(
+        unsafe { host.sender::<u32>("doubler") },
+        Err(RegistryError::TypeMismatch { .. })
+    ));
+}
+
+#[test]
+fn not_a_plugin() {
+    let mut host = PluginHost::new();
+    assert!(matches!(
+        unsafe { host.load("libmicroservices-missing-plugin.so") },
+        Err(PluginError::Library(_))
+    ));
+}